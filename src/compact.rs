@@ -0,0 +1,610 @@
+//! Meshes stored as flat arrays instead of one allocation per node and element.
+use crate::parser::{Diagnostic, MshReader, MshResult, MshVisitor, ReadOptions};
+use crate::writer::{self, EltRef, MeshRecords};
+use crate::*;
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+/// A mesh whose nodes and elements are stored as a struct of arrays.
+///
+/// Coordinates are one flat array, and element nodes and physical groups are
+/// stored in compressed sparse row (CSR) form: element `i` has the nodes
+/// `connectivity()[offsets()[i]..offsets()[i + 1]]`. What most elements don't have,
+/// like node entities or 2.2 partitions, is only stored where it's set. Huge
+/// meshes then take a handful of allocations instead of one or two per element.
+///
+/// The arrays can only be changed through [`push_node`](CompactMsh::push_node)
+/// and [`push_element`](CompactMsh::push_element), which keep them consistent.
+///
+/// Converting to and from [`Msh`] loses nothing. Files can also be read into and
+/// written from a `CompactMsh` directly, without building a [`Msh`] on the way.
+///
+/// ```no_run
+/// # fn main() -> msh_rw::parser::MshResult<()> {
+/// use msh_rw::CompactMsh;
+///
+/// let mesh = CompactMsh::from_file("mesh.msh")?;
+/// let lowest = mesh.coords().chunks(3).map(|point| point[2]).fold(f64::INFINITY, f64::min);
+/// println!("{} elements, the lowest node at z = {}", mesh.num_elements(), lowest);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "CompactMshFields"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactMsh {
+    node_tags: Vec<Tag>,
+    coords: Vec<f64>,
+    node_entities: Vec<(usize, Option<EntityId>)>,
+    parametric: Vec<(usize, [f64; 3])>,
+    elt_tags: Vec<Tag>,
+    elt_types: Vec<MeshShape>,
+    offsets: Vec<usize>,
+    connectivity: Vec<Tag>,
+    group_offsets: Vec<usize>,
+    groups: Vec<Tag>,
+    geometry: Vec<Option<Tag>>,
+    partitions: Vec<(usize, EltPartitioning)>,
+    /// Everything else in the file. Its nodes and elements aren't used.
+    pub rest: Msh,
+}
+
+/// The fields of a [`CompactMsh`], checked to be consistent when deserializing one.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct CompactMshFields {
+    node_tags: Vec<Tag>,
+    coords: Vec<f64>,
+    node_entities: Vec<(usize, Option<EntityId>)>,
+    parametric: Vec<(usize, [f64; 3])>,
+    elt_tags: Vec<Tag>,
+    elt_types: Vec<MeshShape>,
+    offsets: Vec<usize>,
+    connectivity: Vec<Tag>,
+    group_offsets: Vec<usize>,
+    groups: Vec<Tag>,
+    geometry: Vec<Option<Tag>>,
+    partitions: Vec<(usize, EltPartitioning)>,
+    rest: Msh,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<CompactMshFields> for CompactMsh {
+    type Error = &'static str;
+
+    fn try_from(fields: CompactMshFields) -> Result<CompactMsh, &'static str> {
+        let mesh = CompactMsh {
+            node_tags: fields.node_tags,
+            coords: fields.coords,
+            node_entities: fields.node_entities,
+            parametric: fields.parametric,
+            elt_tags: fields.elt_tags,
+            elt_types: fields.elt_types,
+            offsets: fields.offsets,
+            connectivity: fields.connectivity,
+            group_offsets: fields.group_offsets,
+            groups: fields.groups,
+            geometry: fields.geometry,
+            partitions: fields.partitions,
+            rest: fields.rest,
+        };
+        if mesh.is_consistent() {
+            Ok(mesh)
+        } else {
+            Err("inconsistent CompactMsh arrays")
+        }
+    }
+}
+
+/// The `msh` 2.2 tags of a [`MeshElt`] that most elements don't have.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EltPartitioning {
+    pub partitions: Vec<Tag>,
+    pub ghost_partitions: Vec<Tag>,
+    pub parent: Option<Tag>,
+    pub domain: Option<(Tag, Tag)>,
+}
+
+impl CompactMsh {
+    pub fn new() -> CompactMsh {
+        CompactMsh {
+            node_tags: Vec::new(),
+            coords: Vec::new(),
+            node_entities: Vec::new(),
+            parametric: Vec::new(),
+            elt_tags: Vec::new(),
+            elt_types: Vec::new(),
+            offsets: vec![0],
+            connectivity: Vec::new(),
+            group_offsets: vec![0],
+            groups: Vec::new(),
+            geometry: Vec::new(),
+            partitions: Vec::new(),
+            rest: Msh::new(),
+        }
+    }
+
+    /// Like [`Msh::from_file`].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> MshResult<CompactMsh> {
+        CompactMsh::read(std::fs::File::open(path)?)
+    }
+
+    pub fn read<R: Read>(reader: R) -> MshResult<CompactMsh> {
+        Ok(CompactMsh::read_with(reader, &ReadOptions::default())?.0)
+    }
+
+    /// Like [`Msh::read_with`], reading nodes and elements straight into their arrays.
+    pub fn read_with<R: Read>(reader: R, options: &ReadOptions) -> MshResult<(CompactMsh, Vec<Diagnostic>)> {
+        let mut reader = MshReader::with_options(BufReader::new(reader), options);
+        let mut mesh = CompactMsh::new();
+        loop {
+            // concatenated meshes are merged on their own, like `Msh::read_with` does
+            let start = mesh.num_elements();
+            match reader.visit_msh(&mut mesh)? {
+                Some(version) if options.merge_duplicate_elements && version.is_v22() => {
+                    mesh.merge_duplicates_from(start)
+                }
+                Some(_) => (),
+                None => break,
+            }
+        }
+        Ok((mesh, reader.into_diagnostics()))
+    }
+
+    /// Writes the same file as [`Msh::write`] would for this mesh.
+    pub fn write<W: Write>(&self, sink: &mut W, options: &WriteOptions) -> io::Result<()> {
+        writer::write(self, &self.rest, sink, options)
+    }
+
+    pub fn write_msh2<W: Write>(&self, sink: &mut W, storage: Storage) -> io::Result<()> {
+        self.write(sink, &WriteOptions::new().version(Version::V22).storage(storage))
+    }
+
+    pub fn write_msh4<W: Write>(&self, sink: &mut W, storage: Storage) -> io::Result<()> {
+        self.write(sink, &WriteOptions::new().version(Version::V41).storage(storage))
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.node_tags.len()
+    }
+
+    pub fn num_elements(&self) -> usize {
+        self.elt_tags.len()
+    }
+
+    pub fn node_tags(&self) -> &[Tag] {
+        &self.node_tags
+    }
+
+    /// `x, y, z` for each node.
+    pub fn coords(&self) -> &[f64] {
+        &self.coords
+    }
+
+    /// The entity of each run of nodes sharing one, as the index of the run's
+    /// first node. Runs are in order, and nodes before the first have no entity.
+    pub fn node_entities(&self) -> &[(usize, Option<EntityId>)] {
+        &self.node_entities
+    }
+
+    /// Parametric coordinates of the nodes that have them, by node index in order.
+    pub fn parametric(&self) -> &[(usize, [f64; 3])] {
+        &self.parametric
+    }
+
+    pub fn elt_tags(&self) -> &[Tag] {
+        &self.elt_tags
+    }
+
+    pub fn elt_types(&self) -> &[MeshShape] {
+        &self.elt_types
+    }
+
+    /// Where the nodes of each element start in [`connectivity`](CompactMsh::connectivity), plus its length.
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+
+    pub fn connectivity(&self) -> &[Tag] {
+        &self.connectivity
+    }
+
+    /// Where the physical groups of each element start in [`groups`](CompactMsh::groups), plus its length.
+    pub fn group_offsets(&self) -> &[usize] {
+        &self.group_offsets
+    }
+
+    pub fn groups(&self) -> &[Tag] {
+        &self.groups
+    }
+
+    pub fn geometry(&self) -> &[Option<Tag>] {
+        &self.geometry
+    }
+
+    /// 2.2 partitions, parents and domains of the elements that have any, by
+    /// element index in order.
+    pub fn partitions(&self) -> &[(usize, EltPartitioning)] {
+        &self.partitions
+    }
+
+    pub fn node(&self, i: usize) -> Option<Node> {
+        if i < self.num_nodes() {
+            Some(self.node_at(i))
+        } else {
+            None
+        }
+    }
+
+    pub fn element(&self, i: usize) -> Option<MeshElt> {
+        if i < self.num_elements() {
+            let elt = self.elt_at(i);
+            Some(MeshElt {
+                tag: elt.tag,
+                ty: elt.ty,
+                nodes: elt.nodes.to_vec(),
+                physical_groups: elt.physical_groups.to_vec(),
+                geometry: elt.geometry,
+                partitions: elt.partitions.to_vec(),
+                ghost_partitions: elt.ghost_partitions.to_vec(),
+                parent: elt.parent,
+                domain: elt.domain,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn element_nodes(&self, i: usize) -> Option<&[Tag]> {
+        Some(&self.connectivity[*self.offsets.get(i)?..*self.offsets.get(i + 1)?])
+    }
+
+    pub fn element_groups(&self, i: usize) -> Option<&[Tag]> {
+        Some(&self.groups[*self.group_offsets.get(i)?..*self.group_offsets.get(i + 1)?])
+    }
+
+    pub fn push_node(&mut self, node: Node) {
+        let i = self.num_nodes();
+        self.node_tags.push(node.tag);
+        self.coords.extend(&[node.x, node.y, node.z]);
+        if self.node_entities.last().map(|&(_, entity)| entity) != Some(node.entity) {
+            self.node_entities.push((i, node.entity));
+        }
+        if let Some(parametric) = node.parametric {
+            self.parametric.push((i, parametric));
+        }
+    }
+
+    pub fn push_element(&mut self, elt: &MeshElt) {
+        self.push_elt(EltRef::from(elt));
+    }
+
+    /// Like [`Msh::merge_duplicate_elements`].
+    pub fn merge_duplicate_elements(&mut self) {
+        self.merge_duplicates_from(0);
+    }
+
+    /// Merges the duplicates among the elements from `start` on.
+    fn merge_duplicates_from(&mut self, start: usize) {
+        let mut first: HashMap<EltRef, usize> = HashMap::new();
+        // the elements kept and the groups they take from their duplicates
+        let mut kept: Vec<(usize, Vec<Tag>)> = Vec::with_capacity(self.num_elements() - start);
+        for i in start..self.num_elements() {
+            let elt = self.elt_at(i);
+            match first.entry(elt.duplicate_key()) {
+                Entry::Occupied(index) => {
+                    let (kept_elt, extra) = &mut kept[*index.get()];
+                    let groups = self.elt_at(*kept_elt).physical_groups;
+                    for &group in elt.physical_groups {
+                        if !groups.contains(&group) && !extra.contains(&group) {
+                            extra.push(group);
+                        }
+                    }
+                }
+                Entry::Vacant(index) => {
+                    index.insert(kept.len());
+                    kept.push((i, Vec::new()));
+                }
+            }
+        }
+        if start + kept.len() == self.num_elements() {
+            return;
+        }
+
+        let mut merged = CompactMsh::new();
+        let mut groups = Vec::new();
+        for (i, extra) in kept {
+            let mut elt = self.elt_at(i);
+            groups.clear();
+            groups.extend_from_slice(elt.physical_groups);
+            groups.extend(extra);
+            elt.physical_groups = &groups;
+            merged.push_elt(elt);
+        }
+        self.truncate_elements(start);
+        for i in 0..merged.num_elements() {
+            self.push_elt(merged.elt_at(i));
+        }
+    }
+
+    /// Keeps only the first `len` elements.
+    fn truncate_elements(&mut self, len: usize) {
+        self.elt_tags.truncate(len);
+        self.elt_types.truncate(len);
+        self.offsets.truncate(len + 1);
+        self.connectivity.truncate(self.offsets[len]);
+        self.group_offsets.truncate(len + 1);
+        self.groups.truncate(self.group_offsets[len]);
+        self.geometry.truncate(len);
+        let partitioned = self.partitions.iter().take_while(|&&(elt, _)| elt < len).count();
+        self.partitions.truncate(partitioned);
+    }
+
+    /// Whether the arrays agree with each other, which is always the case unless
+    /// they were deserialized.
+    #[cfg(feature = "serde")]
+    fn is_consistent(&self) -> bool {
+        fn increasing<T>(entries: &[(usize, T)], len: usize) -> bool {
+            let last = match entries.last() {
+                Some(entry) => entry.0 < len,
+                None => true,
+            };
+            last && entries.windows(2).all(|pair| pair[0].0 < pair[1].0)
+        }
+        fn csr(offsets: &[usize], values: usize, len: usize) -> bool {
+            offsets.len() == len + 1
+                && offsets[0] == 0
+                && offsets.windows(2).all(|pair| pair[0] <= pair[1])
+                && offsets[len] == values
+        }
+        let (num_nodes, num_elts) = (self.num_nodes(), self.num_elements());
+        self.coords.len() == 3 * num_nodes
+            && increasing(&self.node_entities, num_nodes)
+            && increasing(&self.parametric, num_nodes)
+            && self.elt_types.len() == num_elts
+            && self.geometry.len() == num_elts
+            && csr(&self.offsets, self.connectivity.len(), num_elts)
+            && csr(&self.group_offsets, self.groups.len(), num_elts)
+            && increasing(&self.partitions, num_elts)
+    }
+
+    fn node_at(&self, i: usize) -> Node {
+        let run = match self.node_entities.binary_search_by_key(&i, |&(start, _)| start) {
+            Ok(run) => Some(run),
+            Err(next) => next.checked_sub(1),
+        };
+        let entity = run.and_then(|run| self.node_entities[run].1);
+        let parametric = self
+            .parametric
+            .binary_search_by_key(&i, |&(node, _)| node)
+            .ok()
+            .map(|k| self.parametric[k].1);
+        let point = &self.coords[3 * i..3 * i + 3];
+        Node { tag: self.node_tags[i], x: point[0], y: point[1], z: point[2], entity, parametric }
+    }
+
+    fn elt_at(&self, i: usize) -> EltRef<'_> {
+        let partitioning = self
+            .partitions
+            .binary_search_by_key(&i, |&(elt, _)| elt)
+            .ok()
+            .map(|k| &self.partitions[k].1);
+        EltRef {
+            tag: self.elt_tags[i],
+            ty: self.elt_types[i],
+            nodes: &self.connectivity[self.offsets[i]..self.offsets[i + 1]],
+            physical_groups: &self.groups[self.group_offsets[i]..self.group_offsets[i + 1]],
+            geometry: self.geometry[i],
+            partitions: partitioning.map_or(&[], |p| &p.partitions),
+            ghost_partitions: partitioning.map_or(&[], |p| &p.ghost_partitions),
+            parent: partitioning.and_then(|p| p.parent),
+            domain: partitioning.and_then(|p| p.domain),
+        }
+    }
+
+    fn push_elt(&mut self, elt: EltRef) {
+        let i = self.num_elements();
+        self.elt_tags.push(elt.tag);
+        self.elt_types.push(elt.ty);
+        self.connectivity.extend_from_slice(elt.nodes);
+        self.offsets.push(self.connectivity.len());
+        self.groups.extend_from_slice(elt.physical_groups);
+        self.group_offsets.push(self.groups.len());
+        self.geometry.push(elt.geometry);
+        let partitioned = !elt.partitions.is_empty()
+            || !elt.ghost_partitions.is_empty()
+            || elt.parent.is_some()
+            || elt.domain.is_some();
+        if partitioned {
+            self.partitions.push((
+                i,
+                EltPartitioning {
+                    partitions: elt.partitions.to_vec(),
+                    ghost_partitions: elt.ghost_partitions.to_vec(),
+                    parent: elt.parent,
+                    domain: elt.domain,
+                },
+            ));
+        }
+    }
+}
+
+impl Default for CompactMsh {
+    fn default() -> CompactMsh {
+        CompactMsh::new()
+    }
+}
+
+impl From<Msh> for CompactMsh {
+    fn from(mut msh: Msh) -> CompactMsh {
+        let mut mesh = CompactMsh::new();
+        mesh.node_tags.reserve(msh.nodes.len());
+        mesh.coords.reserve(3 * msh.nodes.len());
+        for node in msh.nodes.drain(..) {
+            mesh.push_node(node);
+        }
+        mesh.elt_tags.reserve(msh.elts.len());
+        mesh.connectivity.reserve(msh.elts.iter().map(|elt| elt.nodes.len()).sum());
+        for elt in msh.elts.drain(..) {
+            mesh.push_element(&elt);
+        }
+        mesh.rest = msh;
+        mesh
+    }
+}
+
+impl From<CompactMsh> for Msh {
+    fn from(mesh: CompactMsh) -> Msh {
+        let nodes = (0..mesh.num_nodes()).map(|i| mesh.node_at(i)).collect();
+        let elts = (0..mesh.num_elements()).filter_map(|i| mesh.element(i)).collect();
+        Msh { nodes, elts, ..mesh.rest }
+    }
+}
+
+impl MeshRecords for CompactMsh {
+    fn num_nodes(&self) -> usize {
+        self.node_tags.len()
+    }
+
+    fn node(&self, i: usize) -> Node {
+        self.node_at(i)
+    }
+
+    fn num_elts(&self) -> usize {
+        self.elt_tags.len()
+    }
+
+    fn elt(&self, i: usize) -> EltRef<'_> {
+        self.elt_at(i)
+    }
+}
+
+/// Pushes nodes and elements onto their arrays and hands the rest to [`CompactMsh::rest`].
+impl MshVisitor for CompactMsh {
+    fn on_physical_group(&mut self, group: PhysicalGroup) {
+        self.rest.on_physical_group(group);
+    }
+
+    fn on_entity(&mut self, entity: Entity) {
+        self.rest.on_entity(entity);
+    }
+
+    fn on_partitioning(&mut self, partitioning: Partitioning) {
+        self.rest.on_partitioning(partitioning);
+    }
+
+    fn on_node(&mut self, node: Node) {
+        self.push_node(node);
+    }
+
+    fn on_element(&mut self, elt: &MeshElt) {
+        self.push_element(elt);
+    }
+
+    fn on_periodic_link(&mut self, link: PeriodicLink) {
+        self.rest.on_periodic_link(link);
+    }
+
+    fn on_parametrizations(&mut self, parametrizations: Parametrizations) {
+        self.rest.on_parametrizations(parametrizations);
+    }
+
+    fn on_interpolation_scheme(&mut self, scheme: InterpolationScheme) {
+        self.rest.on_interpolation_scheme(scheme);
+    }
+
+    fn on_data_view(&mut self, view: DataView) {
+        self.rest.on_data_view(view);
+    }
+
+    fn on_unknown_section(&mut self, section: RawSection) {
+        self.rest.on_unknown_section(section);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROPS: &[&str] = &[
+        "props/v2/tetra-pgroup.msh",
+        "props/v2/weird-groups.msh",
+        "props/v2/basic-twice.msh",
+        "props/v2/views.msh",
+        "props/v2/periodic.msh",
+        "props/v4/square.msh",
+        "props/v4/partitioned.msh",
+        "props/v4/parametric.msh",
+    ];
+
+    #[test]
+    fn lossless() {
+        for path in PROPS {
+            let mut msh = Msh::from_file(path).unwrap();
+            msh.elts[0].partitions = vec![1];
+            msh.elts[0].ghost_partitions = vec![2, 3];
+            msh.elts.last_mut().unwrap().parent = Some(1);
+            let mesh = CompactMsh::from(msh.clone());
+            assert_eq!(mesh.num_nodes(), msh.nodes.len());
+            assert_eq!(mesh.element_nodes(0), Some(&msh.elts[0].nodes[..]));
+            assert_eq!(mesh.element(msh.elts.len()), None);
+            assert_eq!(Msh::from(mesh), msh, "{}", path);
+        }
+    }
+
+    #[test]
+    fn nodes_without_entity_runs() {
+        let mut mesh = CompactMsh::new();
+        mesh.node_tags = vec![1, 2];
+        mesh.coords = vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        assert_eq!(mesh.node(1).map(|node| (node.x, node.entity)), Some((1.0, None)));
+        let entity = Some(EntityId { dim: Dim::from_u8_unchecked(0), tag: 3 });
+        mesh.node_entities = vec![(1, entity)];
+        assert_eq!(mesh.node(0).unwrap().entity, None);
+        assert_eq!(mesh.node(1).unwrap().entity, entity);
+    }
+
+    #[test]
+    fn read_directly() {
+        for path in PROPS {
+            let msh = Msh::from_file(path).unwrap();
+            assert_eq!(CompactMsh::from_file(path).unwrap(), CompactMsh::from(msh), "{}", path);
+        }
+        let keep = ReadOptions { merge_duplicate_elements: false, ..Default::default() };
+        let input = std::fs::read("props/v2/weird-groups.msh").unwrap();
+        let (duplicated, _) = CompactMsh::read_with(input.as_slice(), &keep).unwrap();
+        assert_eq!(duplicated, CompactMsh::from(Msh::read_with(input.as_slice(), &keep).unwrap().0));
+    }
+
+    #[test]
+    fn same_files_as_msh() {
+        for path in PROPS {
+            let msh = Msh::from_file(path).unwrap();
+            let mesh = CompactMsh::from(msh.clone());
+            for &storage in &[Storage::Ascii, Storage::BinaryLe, Storage::BinaryBe] {
+                let (mut expected, mut written) = (Vec::new(), Vec::new());
+                msh.write_msh2(&mut expected, storage).unwrap();
+                mesh.write_msh2(&mut written, storage).unwrap();
+                msh.write_msh4(&mut expected, storage).unwrap();
+                mesh.write_msh4(&mut written, storage).unwrap();
+                assert!(written == expected, "{} ({:?})", path, storage);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_checks_arrays() {
+        let mesh = CompactMsh::from_file("props/v2/tetra-pgroup.msh").unwrap();
+        let json = serde_json::to_string(&mesh).unwrap();
+        assert_eq!(serde_json::from_str::<CompactMsh>(&json).unwrap(), mesh);
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["offsets"][1] = serde_json::json!(1_000_000);
+        assert!(serde_json::from_value::<CompactMsh>(value.clone()).is_err());
+        value["offsets"] = serde_json::json!([]);
+        assert!(serde_json::from_value::<CompactMsh>(value).is_err());
+    }
+}
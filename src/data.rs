@@ -0,0 +1,124 @@
+//! Post-processing data from `$NodeData`, `$ElementData` and `$ElementNodeData` sections.
+use crate::Tag;
+
+/// What the values of a view are attached to.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DataKind {
+    /// `$NodeData`: values per node.
+    Node,
+    /// `$ElementData`: values per element.
+    Element,
+    /// `$ElementNodeData`: values per node of each element.
+    ElementNode,
+}
+
+impl DataKind {
+    /// The section name without its `$`.
+    pub fn section(self) -> &'static str {
+        match self {
+            DataKind::Node => "NodeData",
+            DataKind::Element => "ElementData",
+            DataKind::ElementNode => "ElementNodeData",
+        }
+    }
+}
+
+/// A named field, such as a temperature or displacement, over one or more time steps.
+///
+/// Each data section holds one step, and sections sharing a name and kind make up a view.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataView {
+    /// The first string tag.
+    pub name: String,
+    pub kind: DataKind,
+    /// Steps in the order they were read.
+    pub steps: Vec<DataStep>,
+}
+
+impl DataView {
+    pub fn new(name: impl Into<String>, kind: DataKind) -> DataView {
+        DataView { name: name.into(), kind, steps: Vec::new() }
+    }
+}
+
+/// One data section.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataStep {
+    /// The second string tag, naming an `$InterpolationScheme`.
+    pub interpolation_scheme: Option<String>,
+    /// The first real tag.
+    pub time: f64,
+    /// The first integer tag.
+    pub time_step: u64,
+    /// The second integer tag: 1 for scalars, 3 for vectors and 9 for tensors.
+    pub num_components: usize,
+    /// The optional fourth integer tag. The third, the number of entries, is `entries.len()`.
+    pub partition: Option<u64>,
+    pub entries: Vec<DataEntry>,
+}
+
+/// The values of one node or element.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataEntry {
+    /// A node tag for `$NodeData`, an element tag otherwise.
+    pub tag: Tag,
+    /// `num_components` values, repeated for each of the element's nodes in `$ElementNodeData`.
+    pub values: Vec<f64>,
+}
+
+/// Basis functions for high-order views, from `$InterpolationScheme`.
+///
+/// Steps use a scheme by naming it in [`DataStep::interpolation_scheme`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpolationScheme {
+    pub name: String,
+    pub topologies: Vec<TopologyInterpolation>,
+}
+
+impl InterpolationScheme {
+    pub fn topology(&self, topology: u32) -> Option<&TopologyInterpolation> {
+        self.topologies.iter().find(|interpolation| interpolation.topology == topology)
+    }
+}
+
+/// The matrices of one element family.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyInterpolation {
+    /// Gmsh element family, e.g. 3 for triangles and 5 for tetrahedra.
+    pub topology: u32,
+    /// Usually the coefficient matrix followed by the exponent matrix.
+    pub matrices: Vec<Matrix>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    pub rows: usize,
+    pub columns: usize,
+    /// Row-major values.
+    pub values: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn get(&self, row: usize, column: usize) -> Option<f64> {
+        if row < self.rows && column < self.columns {
+            self.values.get(row * self.columns + column).copied()
+        } else {
+            None
+        }
+    }
+}
+
+/// Adds `step` to the view called `name`, creating it if needed.
+pub(crate) fn add_step(views: &mut Vec<DataView>, name: String, kind: DataKind, step: DataStep) {
+    match views.iter_mut().find(|view| view.name == name && view.kind == kind) {
+        Some(view) => view.steps.push(step),
+        None => views.push(DataView { name, kind, steps: vec![step] }),
+    }
+}
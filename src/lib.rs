@@ -3,11 +3,26 @@ pub type Tag = u64; // always write out 8-byte tags
 
 pub mod parser;
 pub mod mesh;
+mod compact;
+mod data;
+mod parametrization;
+mod partition;
+mod shape;
+mod writer;
 
-use std::io::{self, Write};
+pub use compact::{CompactMsh, EltPartitioning};
+pub use data::{DataEntry, DataKind, DataStep, DataView, InterpolationScheme, Matrix, TopologyInterpolation};
+pub use parametrization::{CurveParametrization, Parametrizations, SurfaceParametrization, SurfaceParametrizationNode};
+pub use partition::{GhostEntity, PartitionedEntity, Partitioning};
+pub use shape::MeshShape;
+pub use writer::{FloatFormat, WriteOptions};
+
+use writer::EltRef;
+
+use std::collections::hash_map::{Entry, HashMap};
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Dim {
     dim: u8,
 }
@@ -22,23 +37,77 @@ impl Dim {
     pub fn from_u8_unchecked(dim: u8) -> Dim {
         Dim { dim }
     }
+    pub fn as_u8(self) -> u8 {
+        self.dim
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PhysicalGroup {
     pub dim: Dim,
     pub tag: Tag,
     pub name: String,
 }
 
+/// Identifies a geometric entity by its dimension and tag.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EntityId {
+    pub dim: Dim,
+    pub tag: Tag,
+}
+
+/// A point, curve, surface or volume from the `$Entities` section (`msh` 4.1 only).
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub dim: Dim,
+    pub tag: Tag,
+    /// Bounding box corners. Points store their coordinates in both.
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+    pub physical_groups: Vec<Tag>,
+    /// Tags of the bounding entities one dimension down, signed by orientation.
+    pub boundary: Vec<i64>,
+}
+
+impl Entity {
+    pub fn id(&self) -> EntityId {
+        EntityId { dim: self.dim, tag: self.tag }
+    }
+}
+
+/// An entity whose mesh is a copy of another's, from the `$Periodic` section.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodicLink {
+    pub dim: Dim,
+    /// The slave entity.
+    pub entity: Tag,
+    pub master: Tag,
+    /// Row-major 4x4 affine transform mapping the master onto the slave.
+    pub affine: Option<[f64; 16]>,
+    /// Slave node tags and their master node tags.
+    pub nodes: Vec<(Tag, Tag)>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Msh {
     pub nodes: Vec<Node>,
     pub elts: Vec<MeshElt>,
     pub physical_groups: Vec<PhysicalGroup>,
-    // entity blocs -- need to match to nodes
+    pub entities: Vec<Entity>,
+    /// Set for partitioned meshes, whose entities come from `$PartitionedEntities`.
+    pub partitioning: Option<Partitioning>,
+    pub periodic: Vec<PeriodicLink>,
+    pub parametrizations: Parametrizations,
+    pub interpolation_schemes: Vec<InterpolationScheme>,
+    /// Post-processing views from the data sections.
+    pub views: Vec<DataView>,
+    /// Sections that aren't interpreted, e.g. `$Comments`, in the order they were read.
+    pub unknown_sections: Vec<RawSection>,
 }
 
 impl Msh {
@@ -47,34 +116,93 @@ impl Msh {
             nodes: Vec::new(),
             elts: Vec::new(),
             physical_groups: Vec::new(),
+            entities: Vec::new(),
+            partitioning: None,
+            periodic: Vec::new(),
+            parametrizations: Parametrizations::default(),
+            interpolation_schemes: Vec::new(),
+            views: Vec::new(),
+            unknown_sections: Vec::new(),
         }
     }
 
-    pub fn write_msh2<W: Write>(&self, sink: &mut W, storage: Storage) -> io::Result<()> {
-        write!(sink, "{}", MshHeader { version: Version::V22, storage })?;
-        writeln!(sink, "$Nodes")?;
-        writeln!(sink, "{}", self.nodes.len())?;
-        for node in &self.nodes {
-            match storage {
-                Storage::Ascii => writeln!(sink, "{} {} {} {}", node.tag, node.x, node.y, node.z)?,
-                Storage::BinaryLe => {
-                    sink.write_all(&node.tag.to_le_bytes())?;
-                    sink.write_all(&node.x.to_le_bytes())?;
-                    sink.write_all(&node.y.to_le_bytes())?;
-                    sink.write_all(&node.z.to_le_bytes())?
-                },
+    pub fn entity(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.iter().find(|entity| entity.id() == id)
+    }
+
+    /// Folds elements that only differ in their tag and physical groups into the first one, joining the groups.
+    ///
+    /// 2.2 files list an element once for each physical group it belongs to.
+    /// Reading 2.2 files does this unless [`ReadOptions::merge_duplicate_elements`] is off.
+    ///
+    /// [`ReadOptions::merge_duplicate_elements`]: parser::ReadOptions::merge_duplicate_elements
+    pub fn merge_duplicate_elements(&mut self) {
+        // for each element, the earlier one it folds into, as an index into the merged elements
+        let mut targets: Vec<Option<usize>> = Vec::with_capacity(self.elts.len());
+        let mut num_kept = 0;
+        let mut first: HashMap<EltRef, usize> = HashMap::new();
+        for elt in &self.elts {
+            match first.entry(EltRef::from(elt).duplicate_key()) {
+                Entry::Occupied(index) => targets.push(Some(*index.get())),
+                Entry::Vacant(index) => {
+                    index.insert(num_kept);
+                    targets.push(None);
+                    num_kept += 1;
+                }
             }
         }
-        writeln!(sink, "$EndNodes")?;
-        Ok(())
+        if num_kept == self.elts.len() {
+            return;
+        }
+
+        let mut merged: Vec<MeshElt> = Vec::with_capacity(num_kept);
+        for (elt, target) in self.elts.drain(..).zip(targets) {
+            match target {
+                Some(index) => {
+                    let groups = &mut merged[index].physical_groups;
+                    for group in elt.physical_groups {
+                        if !groups.contains(&group) {
+                            groups.push(group);
+                        }
+                    }
+                }
+                None => merged.push(elt),
+            }
+        }
+        self.elts = merged;
+    }
+
+    /// The node `node` is a periodic copy of, if any.
+    pub fn periodic_master(&self, node: Tag) -> Option<Tag> {
+        self.periodic
+            .iter()
+            .flat_map(|link| &link.nodes)
+            .find(|&&(slave, _)| slave == node)
+            .map(|&(_, master)| master)
     }
 
-    pub fn write_msh4<W: Write>(&self, sink: &mut W, storage: Storage) -> io::Result<()> {
-        write!(sink, "{}", MshHeader{ version: Version::V41, storage })?;
-        Ok(())
+    pub fn view(&self, name: &str) -> Option<&DataView> {
+        self.views.iter().find(|view| view.name == name)
+    }
+
+    /// The interpolation scheme `step` names, if any.
+    pub fn interpolation_scheme(&self, step: &DataStep) -> Option<&InterpolationScheme> {
+        let name = step.interpolation_scheme.as_ref()?;
+        self.interpolation_schemes.iter().find(|scheme| &scheme.name == name)
     }
 }
 
+/// A section kept as is, so it can be written back unchanged.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawSection {
+    /// The name without its `$`, e.g. `Comments`.
+    pub name: String,
+    /// Everything between the opening and closing lines, including the final newline.
+    /// Binary files can store binary data here.
+    pub contents: Vec<u8>,
+}
+
 /// Only `size_t` of 8 bytes is supported (like Gmsh itself).
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MshHeader {
@@ -86,7 +214,7 @@ pub struct MshHeader {
 pub enum Version { V22, V41 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum Storage { Ascii, BinaryLe }
+pub enum Storage { Ascii, BinaryLe, BinaryBe }
 
 impl std::fmt::Display for MshHeader {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
@@ -99,69 +227,68 @@ impl std::fmt::Display for MshHeader {
         };
         let storage = match self.storage {
             Ascii => "0",
-            BinaryLe => "1",
+            BinaryLe | BinaryBe => "1",
         };
         writeln!(fmt, "{} {} 8", vers, storage)?;
         match self.storage {
             Ascii => (/* skip */),
             BinaryLe => writeln!(fmt, "\u{1}\u{0}\u{0}\u{0}")?,
+            BinaryBe => writeln!(fmt, "\u{0}\u{0}\u{0}\u{1}")?,
         };
         writeln!(fmt, "$EndMeshFormat")
     }
 }
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Node {
     pub tag: Tag,
     pub x: f64,
     pub y: f64,
     pub z: f64,
+    /// The entity this node is classified on (`msh` 4.1 only).
+    pub entity: Option<EntityId>,
+    /// Coordinates in the parameter space of `entity`: `u` on curves, `u, v` on
+    /// surfaces and `u, v, w` in volumes, with the rest zero (`msh` 4.1 only).
+    pub parametric: Option<[f64; 3]>,
 }
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MeshElt {
     pub tag: Tag,
     pub ty: MeshShape,
     pub nodes: Vec<Tag>,
-    /// The element's physical group.
-    pub physical_group: Option<Tag>,
-    /// The geometry this element comes from.
+    /// The element's physical groups.
+    ///
+    /// 2.2 files repeat an element once per group, which [`Msh::merge_duplicate_elements`] folds into one.
+    pub physical_groups: Vec<Tag>,
+    /// The geometry this element comes from, an entity of the element's dimension.
     pub geometry: Option<Tag>,
+    /// Mesh partitions owning the element (`msh` 2.2 only).
+    pub partitions: Vec<Tag>,
+    /// Mesh partitions holding a ghost copy of the element (`msh` 2.2 only).
+    pub ghost_partitions: Vec<Tag>,
+    /// The element this one was refined from (`msh` 2.2 only).
+    pub parent: Option<Tag>,
+    /// The entities on either side of an interface element (`msh` 2.2 only).
+    /// Files store either a parent or a domain, so this is only written without a parent.
+    pub domain: Option<(Tag, Tag)>,
 }
 
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Copy, Clone)]
-pub enum MeshShape {
-    Point,
-    Line,
-    Triangle,
-    Quad,
-    Tetrahedron,
-}
-
-impl MeshShape {
-    pub fn from_gmsh_label(label: &str) -> Option<MeshShape> {
-        match label {
-            "1" => Some(MeshShape::Line),
-            "2" => Some(MeshShape::Triangle),
-            "3" => Some(MeshShape::Quad),
-            "4" => Some(MeshShape::Tetrahedron),
-            "15" => Some(MeshShape::Point),
-            other => {
-                None
-            }
-        }
-    }
-
-    pub fn num_nodes(self) -> u8 {
-        match self {
-            MeshShape::Point => 1,
-            MeshShape::Line => 2,
-            MeshShape::Triangle => 3,
-            MeshShape::Quad => 4,
-            MeshShape::Tetrahedron => 4,
+impl MeshElt {
+    /// An element without group, geometry or partitions.
+    pub fn new(tag: Tag, ty: MeshShape, nodes: Vec<Tag>) -> MeshElt {
+        MeshElt {
+            tag,
+            ty,
+            nodes,
+            physical_groups: Vec::new(),
+            geometry: None,
+            partitions: Vec::new(),
+            ghost_partitions: Vec::new(),
+            parent: None,
+            domain: None,
         }
     }
 }
@@ -169,7 +296,7 @@ impl MeshShape {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use insta::{assert_debug_snapshot, assert_display_snapshot};
+    use insta::assert_debug_snapshot;
 
     #[test]
     fn write_empty_msh2_ascii() {
@@ -191,38 +318,376 @@ mod tests {
     fn basic_msh2_ascii() {
         let mut msh = Msh::new();
         msh.nodes = vec![
-            Node { tag: 1, x: 0.0, y: 0.0, z: 0.0, },
-            Node { tag: 2, x: 1.0, y: 0.0, z: 0.0, },
+            Node { tag: 1, x: 0.0, y: 0.0, z: 0.0, entity: None, parametric: None },
+            Node { tag: 2, x: 1.0, y: 0.0, z: 0.0, entity: None, parametric: None },
         ];
-        msh.elts = vec![MeshElt {
-                tag: 1,
-                ty: MeshShape::Line,
-                nodes: vec![1, 2],
-                physical_group: None,
-                geometry: None,
+        msh.elts = vec![MeshElt::new(1, MeshShape::Line, vec![1, 2])];
+        let mut buffer = Vec::new();
+        msh.write_msh2(&mut buffer, Storage::Ascii).unwrap();
+        assert_debug_snapshot!(String::from_utf8(buffer).unwrap());
+    }
+
+    #[test]
+    fn msh2_round_trip() {
+        let msh = Msh::from_file("props/v2/tetra-pgroup.msh").unwrap();
+        assert!(!msh.physical_groups.is_empty());
+        for &storage in &[Storage::Ascii, Storage::BinaryLe] {
+            let mut buffer = Vec::new();
+            msh.write_msh2(&mut buffer, storage).unwrap();
+            assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+        }
+    }
+
+    #[test]
+    fn unknown_sections_round_trip() {
+        let mut msh = Msh::from_file("props/v2/comments.msh").unwrap();
+        assert_eq!(msh.elts.len(), 1);
+        let names: Vec<_> = msh.unknown_sections.iter().map(|section| section.name.as_str()).collect();
+        assert_eq!(names, vec!["Comments", "Provenance"]);
+        let mut buffer = Vec::new();
+        msh.write_msh2(&mut buffer, Storage::Ascii).unwrap();
+        assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+        // binary contents survive binary files
+        msh.unknown_sections[0].contents = vec![0, 1, 2, 255, b'\n'];
+        for &storage in &[Storage::BinaryLe, Storage::BinaryBe] {
+            for &version in &[Version::V22, Version::V41] {
+                let mut buffer = Vec::new();
+                match version {
+                    Version::V22 => msh.write_msh2(&mut buffer, storage).unwrap(),
+                    Version::V41 => msh.write_msh4(&mut buffer, storage).unwrap(),
+                }
+                let read = Msh::read(buffer.as_slice()).unwrap();
+                assert_eq!(read.unknown_sections, msh.unknown_sections);
+            }
+        }
+    }
+
+    #[test]
+    fn merged_physical_groups() {
+        let input = std::fs::read("props/v2/weird-groups.msh").unwrap();
+        let keep = parser::ReadOptions { merge_duplicate_elements: false, ..Default::default() };
+        let (duplicated, _) = Msh::read_with(input.as_slice(), &keep).unwrap();
+        let msh = Msh::read(input.as_slice()).unwrap();
+        assert!(msh.elts.len() < duplicated.elts.len());
+        let tetra = msh.elts.iter().find(|elt| elt.nodes == vec![11, 12, 10, 13]).unwrap();
+        assert_eq!(tetra.physical_groups, vec![1, 4]);
+        // written back as one record per group
+        for &storage in &[Storage::Ascii, Storage::BinaryLe] {
+            let mut buffer = Vec::new();
+            msh.write_msh2(&mut buffer, storage).unwrap();
+            assert_eq!(Msh::read_with(buffer.as_slice(), &keep).unwrap().0.elts.len(), duplicated.elts.len());
+            assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+        }
+    }
+
+    #[test]
+    fn merge_needs_equal_elements() {
+        let mut elt = MeshElt::new(1, MeshShape::Line, vec![1, 2]);
+        elt.physical_groups = vec![1];
+        let mut copy = MeshElt { tag: 2, physical_groups: vec![2], ..elt.clone() };
+        let other_geometry = MeshElt { tag: 3, geometry: Some(4), ..elt.clone() };
+        let other_parent = MeshElt { tag: 4, parent: Some(1), ..elt.clone() };
+        let mut msh = Msh::new();
+        msh.elts = vec![elt.clone(), copy.clone(), other_geometry.clone(), other_parent.clone()];
+        msh.merge_duplicate_elements();
+        copy.tag = 1;
+        copy.physical_groups = vec![1, 2];
+        assert_eq!(msh.elts, vec![copy, other_geometry, other_parent]);
+    }
+
+    #[test]
+    fn no_merge_in_msh4() {
+        let mut msh = Msh::from_file("props/v2/weird-groups.msh").unwrap();
+        let tetra = msh.elts.iter().position(|elt| elt.nodes == vec![11, 12, 10, 13]).unwrap();
+        let mut copy = msh.elts[tetra].clone();
+        copy.tag = msh.elts.iter().map(|elt| elt.tag).max().unwrap() + 1;
+        msh.elts.push(copy);
+        let mut buffer = Vec::new();
+        msh.write_msh4(&mut buffer, Storage::Ascii).unwrap();
+        assert_eq!(Msh::read(buffer.as_slice()).unwrap().elts.len(), msh.elts.len());
+        assert_eq!(CompactMsh::read(buffer.as_slice()).unwrap().num_elements(), msh.elts.len());
+    }
+
+    #[test]
+    fn element_tags_round_trip() {
+        let mut msh = Msh::from_file("props/v2/tetra-pgroup.msh").unwrap();
+        msh.elts[0].partitions = vec![1];
+        msh.elts[0].ghost_partitions = vec![2, 3];
+        msh.elts[1].parent = Some(1);
+        msh.elts[2].domain = Some((4, 5));
+        for &storage in &[Storage::Ascii, Storage::BinaryLe, Storage::BinaryBe] {
+            let mut buffer = Vec::new();
+            msh.write_msh2(&mut buffer, storage).unwrap();
+            assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+        }
+    }
+
+    #[test]
+    fn periodic_round_trip() {
+        let msh = Msh::from_file("props/v2/periodic.msh").unwrap();
+        assert_eq!(msh.periodic.len(), 2);
+        assert_eq!(msh.periodic[0].affine.unwrap()[7], 1.0);
+        assert_eq!(msh.periodic[1].affine, None);
+        assert_eq!(msh.periodic_master(3), Some(2));
+        assert_eq!(msh.periodic_master(4), Some(1));
+        assert_eq!(msh.periodic_master(1), None);
+        for &storage in &[Storage::Ascii, Storage::BinaryLe, Storage::BinaryBe] {
+            for &version in &[Version::V22, Version::V41] {
+                let mut buffer = Vec::new();
+                match version {
+                    Version::V22 => msh.write_msh2(&mut buffer, storage).unwrap(),
+                    Version::V41 => msh.write_msh4(&mut buffer, storage).unwrap(),
+                }
+                assert_eq!(Msh::read(buffer.as_slice()).unwrap().periodic, msh.periodic);
+            }
+        }
+    }
+
+    #[test]
+    fn views_round_trip() {
+        let msh = Msh::from_file("props/v2/views.msh").unwrap();
+        let names: Vec<_> = msh.views.iter().map(|view| (view.name.as_str(), view.kind)).collect();
+        assert_eq!(
+            names,
+            vec![("temperature", DataKind::Node), ("velocity", DataKind::Element), ("stress", DataKind::ElementNode)]
+        );
+        let temperature = msh.view("temperature").unwrap();
+        let times: Vec<_> = temperature.steps.iter().map(|step| (step.time_step, step.time)).collect();
+        assert_eq!(times, vec![(0, 0.0), (1, 0.5)]);
+        let velocity = &msh.view("velocity").unwrap().steps[0];
+        assert_eq!(velocity.interpolation_scheme.as_deref(), Some("scheme"));
+        assert_eq!((velocity.num_components, velocity.partition), (3, Some(2)));
+        let scheme = msh.interpolation_scheme(velocity).unwrap();
+        assert_eq!(scheme.topology(3).unwrap().matrices[1].values, vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        assert!(msh.interpolation_scheme(&temperature.steps[0]).is_none());
+        assert_eq!(msh.view("stress").unwrap().steps[0].entries[0].values, vec![1.0, 2.0, 3.0]);
+        for &storage in &[Storage::Ascii, Storage::BinaryLe, Storage::BinaryBe] {
+            for &version in &[Version::V22, Version::V41] {
+                let mut buffer = Vec::new();
+                match version {
+                    Version::V22 => msh.write_msh2(&mut buffer, storage).unwrap(),
+                    Version::V41 => msh.write_msh4(&mut buffer, storage).unwrap(),
+                }
+                let read = Msh::read(buffer.as_slice()).unwrap();
+                assert_eq!(read.interpolation_schemes, msh.interpolation_schemes);
+                assert_eq!(read.views, msh.views);
             }
+        }
+    }
+
+    #[test]
+    fn big_endian_round_trip() {
+        for path in &["props/v2/tetra-pgroup.msh", "props/v4/square.msh"] {
+            let msh = Msh::from_file(path).unwrap();
+            let (mut le, mut be) = (Vec::new(), Vec::new());
+            if path.contains("v2") {
+                msh.write_msh2(&mut le, Storage::BinaryLe).unwrap();
+                msh.write_msh2(&mut be, Storage::BinaryBe).unwrap();
+            } else {
+                msh.write_msh4(&mut le, Storage::BinaryLe).unwrap();
+                msh.write_msh4(&mut be, Storage::BinaryBe).unwrap();
+            }
+            assert_ne!(le, be);
+            assert_eq!(le.len(), be.len());
+            assert_eq!(Msh::read(be.as_slice()).unwrap(), msh);
+        }
+    }
+
+    #[test]
+    fn msh2_binary_element_blocks() {
+        // Gmsh wrote one block per element here, we group runs of the same type
+        let original = std::fs::read("props/v2/bin-tetra.msh").unwrap();
+        let msh = Msh::read(original.as_slice()).unwrap();
+        let mut buffer = Vec::new();
+        msh.write_msh2(&mut buffer, Storage::BinaryLe).unwrap();
+        assert!(buffer.len() < original.len());
+        assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+    }
+
+    #[test]
+    fn msh4_round_trip() {
+        let msh = Msh::from_file("props/v4/square.msh").unwrap();
+        for &storage in &[Storage::Ascii, Storage::BinaryLe] {
+            let mut buffer = Vec::new();
+            msh.write_msh4(&mut buffer, storage).unwrap();
+            assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+        }
+    }
+
+    #[test]
+    fn msh4_from_msh2() {
+        let msh2 = Msh::from_file("props/v2/tetra-pgroup.msh").unwrap();
+        let mut buffer = Vec::new();
+        msh2.write_msh4(&mut buffer, Storage::Ascii).unwrap();
+        let msh4 = Msh::read(buffer.as_slice()).unwrap();
+        assert_eq!(msh4.physical_groups, msh2.physical_groups);
+        assert_eq!(msh4.nodes.len(), msh2.nodes.len());
+        for (elt4, elt2) in msh4.elts.iter().zip(&msh2.elts) {
+            assert_eq!((elt4.tag, elt4.ty, &elt4.nodes), (elt2.tag, elt2.ty, &elt2.nodes));
+            assert_eq!(elt4.geometry, elt2.geometry);
+        }
+        // every node sits on the lowest-dimensional entity using it
+        for node in &msh4.nodes {
+            let entity = msh4.entity(node.entity.unwrap()).unwrap();
+            assert!(entity.min[0] <= node.x && node.x <= entity.max[0]);
+        }
+    }
+
+    #[test]
+    fn msh4_groups_without_geometry() {
+        let mut msh = Msh::new();
+        msh.nodes = (1..=3).map(|tag| Node { tag, x: tag as f64, y: 0.0, z: 0.0, entity: None, parametric: None }).collect();
+        msh.elts = vec![
+            MeshElt { physical_groups: vec![1], ..MeshElt::new(1, MeshShape::Line, vec![1, 2]) },
+            MeshElt { physical_groups: vec![2], ..MeshElt::new(2, MeshShape::Line, vec![2, 3]) },
         ];
         let mut buffer = Vec::new();
-        msh.write_msh2(&mut buffer, Storage::Ascii).unwrap();
+        msh.write_msh4(&mut buffer, Storage::Ascii).unwrap();
+        let read = Msh::read(buffer.as_slice()).unwrap();
+        let groups: Vec<_> = read.elts.iter().map(|elt| elt.physical_groups.clone()).collect();
+        assert_eq!(groups, vec![vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn basic_msh4_ascii() {
+        let mut msh = Msh::new();
+        msh.nodes = vec![
+            Node { tag: 1, x: 0.0, y: 0.0, z: 0.0, entity: None, parametric: None },
+            Node { tag: 2, x: 1.0, y: 0.0, z: 0.0, entity: None, parametric: None },
+        ];
+        msh.elts = vec![MeshElt { physical_groups: vec![3], ..MeshElt::new(1, MeshShape::Line, vec![1, 2]) }];
+        let mut buffer = Vec::new();
+        msh.write_msh4(&mut buffer, Storage::Ascii).unwrap();
         assert_debug_snapshot!(String::from_utf8(buffer).unwrap());
     }
 
-    #[cfg(feature = "serde")]
     #[test]
-    fn msh_json() {
+    fn large_ascii_tags() {
+        let big = u64::from(u32::MAX) + 7;
         let mut msh = Msh::new();
         msh.nodes = vec![
-            Node { tag: 1, x: 0.0, y: 0.0, z: 0.0, },
-            Node { tag: 2, x: 1.0, y: 0.0, z: 0.0, },
+            Node { tag: big, x: 0.0, y: 0.0, z: 0.0, entity: None, parametric: None },
+            Node { tag: 1, x: 1.0, y: 0.0, z: 0.0, entity: None, parametric: None },
+        ];
+        msh.elts = vec![MeshElt::new(big + 1, MeshShape::Line, vec![big, 1])];
+        let mut buffer = Vec::new();
+        msh.write_msh2(&mut buffer, Storage::Ascii).unwrap();
+        assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+        // binary 2.2 tags are 32-bit
+        let err = msh.write_msh2(&mut Vec::new(), Storage::BinaryLe).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        // element tags are read back as signed
+        msh.elts[0].physical_groups = vec![u64::MAX];
+        let err = msh.write_msh2(&mut Vec::new(), Storage::Ascii).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn exact_ascii_round_trip() {
+        let mut values = vec![0.1, 1.0 / 3.0, -0.0, 1e-7, 2e22, 5e-324, f64::MIN_POSITIVE, f64::MAX, f64::MIN];
+        // and some arbitrary bit patterns
+        let mut bits: u64 = 0x9e37_79b9_7f4a_7c15;
+        while values.len() < 300 {
+            bits ^= bits << 13;
+            bits ^= bits >> 7;
+            bits ^= bits << 17;
+            let value = f64::from_bits(bits);
+            if value.is_finite() {
+                values.push(value);
+            }
+        }
+        let mut msh = Msh::new();
+        msh.nodes = values
+            .chunks(3)
+            .enumerate()
+            .map(|(i, point)| Node { tag: i as Tag + 1, x: point[0], y: point[1], z: point[2], entity: None, parametric: None })
+            .collect();
+        let coords = |msh: &Msh| -> Vec<u64> {
+            msh.nodes.iter().flat_map(|node| vec![node.x.to_bits(), node.y.to_bits(), node.z.to_bits()]).collect()
+        };
+        for &version in &[Version::V22, Version::V41] {
+            let write = |msh: &Msh| {
+                let mut buffer = Vec::new();
+                match version {
+                    Version::V22 => msh.write_msh2(&mut buffer, Storage::Ascii).unwrap(),
+                    Version::V41 => msh.write_msh4(&mut buffer, Storage::Ascii).unwrap(),
+                }
+                buffer
+            };
+            let written = write(&msh);
+            let read = Msh::read(written.as_slice()).unwrap();
+            assert_eq!(coords(&read), coords(&msh));
+            assert!(write(&read) == written);
+        }
+    }
+
+    #[test]
+    fn float_formats() {
+        let mut msh = Msh::new();
+        msh.nodes = vec![Node { tag: 1, x: 2.0, y: -0.125, z: 1e-7, entity: None, parametric: None }];
+        let formats = [
+            (FloatFormat::Shortest, "1 2 -0.125 1e-7"),
+            (FloatFormat::Fixed(3), "1 2.000 -0.125 0.000"),
+            (FloatFormat::Scientific(2), "1 2.00e0 -1.25e-1 1.00e-7"),
         ];
-        msh.elts = vec![MeshElt {
-                tag: 1,
-                ty: MeshShape::Line,
-                nodes: vec![1, 2],
-                physical_group: None,
-                geometry: None,
+        for &(floats, line) in &formats {
+            let mut buffer = Vec::new();
+            let options = WriteOptions::new().version(Version::V22).floats(floats);
+            msh.write(&mut buffer, &options).unwrap();
+            let text = String::from_utf8(buffer).unwrap();
+            assert!(text.contains(&format!("\n{}\n", line)), "{}", text);
+            assert_eq!(Msh::read(text.as_bytes()).unwrap().nodes.len(), 1);
+        }
+    }
+
+    #[test]
+    fn crlf_round_trip() {
+        for path in &["props/v2/periodic.msh", "props/v2/views.msh", "props/v4/parametric.msh", "props/v4/partitioned.msh"] {
+            let msh = Msh::from_file(path).unwrap();
+            for &storage in &[Storage::Ascii, Storage::BinaryLe, Storage::BinaryBe] {
+                for &version in &[Version::V22, Version::V41] {
+                    let options = WriteOptions::new().version(version).storage(storage);
+                    let (mut lf, mut crlf) = (Vec::new(), Vec::new());
+                    msh.write(&mut lf, &options).unwrap();
+                    msh.write(&mut crlf, &options.crlf(true)).unwrap();
+                    assert!(crlf.len() > lf.len());
+                    if storage == Storage::Ascii {
+                        assert_eq!(String::from_utf8(crlf.clone()).unwrap().replace("\r\n", "\n").into_bytes(), lf);
+                    }
+                    let read = Msh::read(crlf.as_slice()).unwrap();
+                    assert_eq!(read, Msh::read(lf.as_slice()).unwrap(), "{} ({:?} {:?})", path, version, storage);
+                }
             }
+        }
+    }
+
+    #[test]
+    fn optional_sections() {
+        let mut msh = Msh::from_file("props/v2/views.msh").unwrap();
+        msh.physical_groups.push(PhysicalGroup { dim: Dim::from_u8_unchecked(2), tag: 1, name: "wall".to_string() });
+        assert!(!msh.views.is_empty() && !msh.interpolation_schemes.is_empty());
+        let options = WriteOptions::new()
+            .version(Version::V22)
+            .physical_names(false)
+            .views(false)
+            .interpolation_schemes(false)
+            .unknown_sections(false);
+        let mut buffer = Vec::new();
+        msh.write(&mut buffer, &options).unwrap();
+        let read = Msh::read(buffer.as_slice()).unwrap();
+        assert!(read.physical_groups.is_empty() && read.views.is_empty() && read.interpolation_schemes.is_empty());
+        assert_eq!((read.nodes, read.elts), (msh.nodes, msh.elts));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn msh_json() {
+        let mut msh = Msh::new();
+        msh.nodes = vec![
+            Node { tag: 1, x: 0.0, y: 0.0, z: 0.0, entity: None, parametric: None },
+            Node { tag: 2, x: 1.0, y: 0.0, z: 0.0, entity: None, parametric: None },
         ];
-        assert_display_snapshot!(serde_json::to_string(&msh).unwrap());
+        msh.elts = vec![MeshElt::new(1, MeshShape::Line, vec![1, 2])];
+        insta::assert_display_snapshot!(serde_json::to_string(&msh).unwrap());
     }
 }
@@ -1,3 +1,4 @@
+#![allow(dead_code)]
 use crate::Node;
 
 // pub trait MeshElt?
@@ -45,7 +46,7 @@ pub struct Quad {
 }
 
 impl Quad {
-    pub fn from_points(a: Node, b: Node, c: Node, d: Node) -> Option<Quad> {
+    pub fn from_points(_a: Node, _b: Node, _c: Node, _d: Node) -> Option<Quad> {
         todo!()
     }
 
@@ -55,7 +56,3 @@ impl Quad {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-}
@@ -0,0 +1,44 @@
+//! Discrete parametrizations of curves and surfaces, from `$Parametrizations` (`msh` 4.1 only).
+use crate::Tag;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Parametrizations {
+    pub curves: Vec<CurveParametrization>,
+    pub surfaces: Vec<SurfaceParametrization>,
+}
+
+impl Parametrizations {
+    pub fn is_empty(&self) -> bool {
+        self.curves.is_empty() && self.surfaces.is_empty()
+    }
+}
+
+/// Points along a curve entity and their parameter.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurveParametrization {
+    pub tag: Tag,
+    /// `x`, `y`, `z` and `u` of each point.
+    pub nodes: Vec<[f64; 4]>,
+}
+
+/// A triangulation of a surface entity in parameter space.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceParametrization {
+    pub tag: Tag,
+    pub nodes: Vec<SurfaceParametrizationNode>,
+    /// Indices into `nodes`.
+    pub triangles: Vec<[usize; 3]>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SurfaceParametrizationNode {
+    pub point: [f64; 3],
+    pub uv: [f64; 2],
+    /// Directions of maximum and minimum curvature.
+    pub curvature_max: [f64; 3],
+    pub curvature_min: [f64; 3],
+}
@@ -1,45 +1,112 @@
 use crate::*;
 
-use thiserror::Error;
-
 use nom::*;
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take, take_until};
-use nom::combinator::{map_res, cut, peek};
-use nom::character::complete::{anychar, char, line_ending, digit1, one_of, space0, space1};
+use nom::bytes::complete::{tag, take, take_until, take_while1};
+use nom::combinator::{map, map_res, cut, opt, peek, recognize, value};
+use nom::character::complete::{char, line_ending, digit1, one_of, space0, space1};
 use nom::multi::{count, many_till};
 use nom::number::complete::double;
-use nom::error::context;
-use nom::sequence::{delimited, preceded, terminated};
-
-use bstr::ByteSlice;
+use nom::sequence::{delimited, pair, preceded, terminated};
 
+use std::convert::TryFrom;
+use std::io::{BufReader, Read};
 use std::path::Path;
 use std::str::FromStr;
 
-#[derive(Error, Debug)]
-pub enum MshError {
-    #[error("IO error ({source})")]
-    Io {
-        #[from]
-        source: std::io::Error,
-    },
-    #[error("parse error:\n{source}")]
-    Parse {
-        #[from]
-        source: nom::Err<(String, nom::error::ErrorKind)>,
+mod data;
+mod diagnostics;
+mod encoding;
+mod error;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod msh2_binary;
+mod msh4;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod stream;
+mod visitor;
+
+use diagnostics::Diagnostics;
+use visitor::Visit;
+use encoding::{Ascii, BinaryBe, BinaryLe, Encoding};
+use error::{fail, in_section, into_msh_error};
+
+pub use diagnostics::{Diagnostic, DiagnosticKind, ReadOptions, Severity};
+pub use error::{Location, MshError, MshResult, ParseError, ParseErrorKind};
+#[cfg(feature = "mmap")]
+pub use mapped::MappedMsh;
+pub use stream::MshReader;
+pub use visitor::MshVisitor;
+
+pub type IResult<I, O> = nom::IResult<I, O, ParseError<I>>;
+
+impl Msh {
+    /// Reads any supported `msh` file, detecting its version and storage from the header.
+    ///
+    /// Concatenated meshes are merged into one, like Gmsh does.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> MshResult<Msh> {
+        Msh::read(std::fs::File::open(path)?)
+    }
+
+    pub fn read<R: Read>(reader: R) -> MshResult<Msh> {
+        Ok(Msh::read_with(reader, &ReadOptions::default())?.0)
+    }
+
+    /// Like [`read`](Msh::read), also returning what was noticed along the way.
+    pub fn read_with<R: Read>(reader: R, options: &ReadOptions) -> MshResult<(Msh, Vec<Diagnostic>)> {
+        let (meshes, diagnostics) = Msh::read_all_with(reader, options)?;
+        let mut meshes = meshes.into_iter();
+        let mut msh = meshes.next().unwrap_or_default();
+        for other in meshes {
+            msh.append(other);
+        }
+        Ok((msh, diagnostics))
+    }
+
+    /// Reads concatenated meshes separately.
+    pub fn read_all<R: Read>(reader: R) -> MshResult<Vec<Msh>> {
+        Ok(Msh::read_all_with(reader, &ReadOptions::default())?.0)
+    }
+
+    /// Like [`read_all`](Msh::read_all), also returning what was noticed along the way.
+    ///
+    /// Nodes and elements are read one record at a time, see [`MshReader`].
+    pub fn read_all_with<R: Read>(reader: R, options: &ReadOptions) -> MshResult<(Vec<Msh>, Vec<Diagnostic>)> {
+        let mut reader = MshReader::with_options(BufReader::new(reader), options);
+        let meshes = reader.by_ref().collect::<MshResult<_>>()?;
+        Ok((meshes, reader.into_diagnostics()))
+    }
+
+    fn append(&mut self, mut other: Msh) {
+        self.nodes.append(&mut other.nodes);
+        self.elts.append(&mut other.elts);
+        self.physical_groups.append(&mut other.physical_groups);
+        self.entities.append(&mut other.entities);
+        self.partitioning = self.partitioning.take().or(other.partitioning);
+        self.periodic.append(&mut other.periodic);
+        self.parametrizations.curves.append(&mut other.parametrizations.curves);
+        self.parametrizations.surfaces.append(&mut other.parametrizations.surfaces);
+        self.interpolation_schemes.append(&mut other.interpolation_schemes);
+        for view in other.views {
+            for step in view.steps {
+                crate::data::add_step(&mut self.views, view.name.clone(), view.kind, step);
+            }
+        }
+        self.unknown_sections.append(&mut other.unknown_sections);
     }
 }
 
-pub type MshResult<T> = std::result::Result<T, MshError>;
+/// Detects a file's version and storage without reading the whole file.
+pub fn msh_version<P: AsRef<Path>>(path: P) -> MshResult<MshVersion> {
+    parse_header(first_four_lines(path)?.as_bytes())
+}
 
-impl Msh {
-    fn from_file<P: AsRef<Path>>(path: P) -> MshResult<Msh> {
-        let (input, header) = msh_header(&first_four_lines(path)?.as_bytes()).unwrap();
-        //match header {
-        //    todo!()
-        //}
-        todo!();
+/// Like [`msh_header`], but with the error located in `input`.
+pub fn parse_header(input: &[u8]) -> MshResult<MshVersion> {
+    match msh_header(input) {
+        Ok((_, version)) => Ok(version),
+        Err(err) => Err(into_msh_error(input, err)),
     }
 }
 
@@ -48,59 +115,161 @@ pub enum Msh2Section {
     Nodes,
     Elements,
     PhysicalGroups,
+    Periodic,
+    InterpolationScheme,
+    Data(DataKind),
     Unknown,
 }
 
-fn parse_msh2_ascii(input: &str) -> IResult<&str, Msh> {
-    let (input, _) = msh2_ascii_header(input)?;
+fn parse_msh2_ascii<'a>(input: &'a str, diagnostics: &Diagnostics) -> IResult<&'a str, Msh> {
+    let (input, header) = msh2_ascii_header(input)?;
     let mut msh = Msh::new();
+    let mut visit = Visit::new(&mut msh).check_nodes(diagnostics.check_nodes());
+    visit.header(header);
     let mut msh_input = input;
     while let Ok((input, section)) = peek_section(msh_input) {
-        let (rest, _) = add_section(&mut msh, section, input)?;
+        let (rest, _) = add_section(&mut visit, section, input, diagnostics)?;
         msh_input = rest;
     }
     Ok((msh_input, msh))
 }
 
+/// Hands the section at the start of `input` to `visit`, for a file with the given header.
+fn parse_section<'a>(
+    visit: &mut Visit,
+    header: MshVersion,
+    input: &'a [u8],
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    match header {
+        MshVersion::AsciiV22 => as_text(input, |i| {
+            let (i, section) = peek_section(i)?;
+            add_section(visit, section, i, diagnostics)
+        }),
+        MshVersion::AsciiV41 => msh4::parse_section::<Ascii>(visit, input, diagnostics),
+        MshVersion::BinaryLeV22 => msh2_binary::parse_section::<BinaryLe>(visit, input, diagnostics),
+        MshVersion::BinaryLeV41 => msh4::parse_section::<BinaryLe>(visit, input, diagnostics),
+        MshVersion::BinaryBeV22 => msh2_binary::parse_section::<BinaryBe>(visit, input, diagnostics),
+        MshVersion::BinaryBeV41 => msh4::parse_section::<BinaryBe>(visit, input, diagnostics),
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MshVersion {
     AsciiV22,
     AsciiV41,
     BinaryLeV22,
     BinaryLeV41,
+    BinaryBeV22,
+    BinaryBeV41,
+}
+
+impl MshVersion {
+    /// Whether elements are listed once per physical group, see [`Msh::merge_duplicate_elements`].
+    pub(crate) fn is_v22(self) -> bool {
+        match self {
+            MshVersion::AsciiV22 | MshVersion::BinaryLeV22 | MshVersion::BinaryBeV22 => true,
+            MshVersion::AsciiV41 | MshVersion::BinaryLeV41 | MshVersion::BinaryBeV41 => false,
+        }
+    }
 }
 
-// careful about input types here! might need to take a &[u8] slice and convert
-// to string for ascii formats.
-pub fn parse_single_msh(input: &str, header: MshVersion) -> IResult<&str, Msh> {
+/// ASCII files are parsed as text, binary files byte-by-byte, so the input is
+/// taken as raw bytes.
+///
+/// Warnings are dropped, see [`parse_msh_file_with`] to keep them.
+pub fn parse_single_msh(input: &[u8], header: MshVersion) -> IResult<&[u8], Msh> {
+    parse_single_msh_with(input, header, &Diagnostics::new(input, &ReadOptions::default()))
+}
+
+fn parse_single_msh_with<'a>(input: &'a [u8], header: MshVersion, diagnostics: &Diagnostics) -> IResult<&'a [u8], Msh> {
     match header {
-        MshVersion::AsciiV22 => parse_msh2_ascii(input),
-        MshVersion::AsciiV41 => todo!(),
-        MshVersion::BinaryLeV22 => todo!(),
-        MshVersion::BinaryLeV41 => todo!(),
+        MshVersion::AsciiV22 => as_text(input, |i| parse_msh2_ascii(i, diagnostics)),
+        MshVersion::AsciiV41 => msh4::parse_msh4::<Ascii>(input, diagnostics),
+        MshVersion::BinaryLeV22 => msh2_binary::parse_msh2_binary::<BinaryLe>(input, diagnostics),
+        MshVersion::BinaryLeV41 => msh4::parse_msh4::<BinaryLe>(input, diagnostics),
+        MshVersion::BinaryBeV22 => msh2_binary::parse_msh2_binary::<BinaryBe>(input, diagnostics),
+        MshVersion::BinaryBeV41 => msh4::parse_msh4::<BinaryBe>(input, diagnostics),
     }
 }
 
 /// Returns a vector of meshes, since two or more concatenated `msh` files are also a valid `msh` file.
-pub fn parse_msh_file(input: &str) -> MshResult<Vec<Msh>> {
-    let mut msh_input = input;
+pub fn parse_msh_file<I: AsRef<[u8]> + ?Sized>(input: &I) -> MshResult<Vec<Msh>> {
+    Ok(parse_msh_file_with(input, &ReadOptions::default())?.0)
+}
+
+/// Like [`parse_msh_file`], also returning what was noticed along the way.
+pub fn parse_msh_file_with<I: AsRef<[u8]> + ?Sized>(
+    input: &I,
+    options: &ReadOptions,
+) -> MshResult<(Vec<Msh>, Vec<Diagnostic>)> {
+    let file = input.as_ref();
+    let diagnostics = Diagnostics::new(file, options);
+    let mut msh_input = file;
     let mut meshes = Vec::new();
 
     while let Ok((input, header)) = peek_header(msh_input) {
-        match parse_single_msh(input, header) {
-            Ok((rest, msh)) => { meshes.push(msh); msh_input = rest },
-            Err(err) => return Err(err.to_owned().into()),
+        match parse_single_msh_with(input, header, &diagnostics) {
+            Ok((rest, mut msh)) => {
+                if options.merge_duplicate_elements && header.is_v22() {
+                    msh.merge_duplicate_elements();
+                }
+                meshes.push(msh);
+                msh_input = rest;
+            }
+            Err(err) => return Err(into_msh_error(file, err)),
         }
     }
 
-    Ok(meshes)
+    Ok((meshes, diagnostics.into_vec()))
 }
 
-fn peek_header(input: &str) -> IResult<&str, MshVersion> {
-    peek(alt((
-        msh2_ascii_header,
-        msh4_ascii_header,
-    )))(input)
+fn peek_header(input: &[u8]) -> IResult<&[u8], MshVersion> {
+    peek(msh_header)(input)
+}
+
+/// Runs a text parser over the leading UTF-8 part of `input`.
+///
+/// Binary data following the text (e.g. a concatenated binary file) is left
+/// untouched in the returned remainder.
+fn as_text<'a, O, F>(input: &'a [u8], parser: F) -> IResult<&'a [u8], O>
+where
+    F: FnOnce(&'a str) -> IResult<&'a str, O>,
+{
+    let text = match std::str::from_utf8(input) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8(&input[..e.valid_up_to()]).unwrap_or_default(),
+    };
+    let to_bytes = |rest: &str| &input[text.len() - rest.len()..];
+    match parser(text) {
+        Ok((rest, out)) => Ok((to_bytes(rest), out)),
+        Err(err) => Err(err.map(|err| err.map_input(to_bytes))),
+    }
+}
+
+/// Runs a byte parser over text, the reverse of [`as_text`].
+fn as_bytes<'a, O, F>(input: &'a str, parser: F) -> IResult<&'a str, O>
+where
+    F: Fn(&'a [u8]) -> IResult<&'a [u8], O>,
+{
+    // byte parsers only stop after ASCII, so the remainder starts on a char boundary
+    let to_text = |rest: &[u8]| &input[input.len() - rest.len()..];
+    match parser(input.as_bytes()) {
+        Ok((rest, out)) => Ok((to_text(rest), out)),
+        Err(err) => Err(err.map(|err| err.map_input(to_text))),
+    }
+}
+
+/// Hands a text section embedded in a byte-level file over to its ASCII parser.
+fn text_section<'a, O, F>(input: &'a [u8], end: &'static str, parser: F) -> IResult<&'a [u8], O>
+where
+    F: Fn(&'a str) -> IResult<&'a str, O>,
+{
+    let (_, body) = take_until(end)(input)?;
+    let (text, rest) = input.split_at(body.len() + end.len());
+    let (_, out) = as_text(text, parser)?;
+    let (rest, _) = eol(rest)?;
+    Ok((rest, out))
 }
 
 fn peek_section(input: &str) -> IResult<&str, Msh2Section> {
@@ -108,28 +277,52 @@ fn peek_section(input: &str) -> IResult<&str, Msh2Section> {
         nodes_header,
         elements_header,
         physical_groups_header,
+        value(Msh2Section::Periodic, terminated(tag("$Periodic"), end_of_line)),
+        value(Msh2Section::InterpolationScheme, terminated(tag("$InterpolationScheme"), end_of_line)),
+        map(|i| as_bytes(i, data::data_header), Msh2Section::Data),
+        value(Msh2Section::Unknown, |i| as_bytes(i, unknown_section_header)),
     )))(input)
 }
 
-fn add_section<'a>(mesh: &mut Msh, section: Msh2Section, input: &'a str) -> IResult<&'a str, ()> {
+fn add_section<'a>(
+    visit: &mut Visit,
+    section: Msh2Section,
+    input: &'a str,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a str, ()> {
     use Msh2Section::*;
     match section {
-        Nodes => {
-            let (rest, nodes) = parse_node_section_msh2(input)?;
-            mesh.nodes = nodes;
+        Nodes => in_section("$Nodes", parse_node_section_msh2(input, visit, diagnostics)),
+        Elements => in_section("$Elements", parse_elements_section_msh2(input, visit, diagnostics)),
+        PhysicalGroups => {
+            let (rest, pgs) = in_section("$PhysicalNames", parse_physical_groups_msh2(input, diagnostics))?;
+            pgs.into_iter().for_each(|group| visit.physical_group(group));
             Ok((rest, ()))
-        },
-        Elements => {
-            let (rest, elts) = parse_elements_section_msh2(input)?;
-            mesh.elts = elts;
+        }
+        Periodic => {
+            let (rest, links) = in_section("$Periodic", parse_periodic_msh2(input))?;
+            links.into_iter().for_each(|link| visit.periodic_link(link));
             Ok((rest, ()))
         }
-        PhysicalGroups => {
-            let (rest, pgs) = parse_physical_groups_msh2(input)?;
-            mesh.physical_groups = pgs;
+        InterpolationScheme => {
+            let (rest, scheme) =
+                in_section("$InterpolationScheme", as_bytes(input, data::parse_interpolation_scheme))?;
+            visit.interpolation_scheme(scheme);
+            Ok((rest, ()))
+        }
+        Data(kind) => {
+            let (rest, (name, kind, step)) = in_section(
+                data::section_name(kind),
+                as_bytes(input, |i| data::parse_data::<Ascii, _>(i, Ascii::size_t)),
+            )?;
+            visit.data_step(name, kind, step);
+            Ok((rest, ()))
+        }
+        Unknown => {
+            let (rest, section) = as_bytes(input, |i| parse_unknown_section(i, diagnostics))?;
+            visit.unknown_section(section);
             Ok((rest, ()))
         }
-        _ => todo!(),
     }
 }
 
@@ -160,7 +353,8 @@ fn headers() {
     assert!(elements_header("$Elements").unwrap().1 == Msh2Section::Elements);
 }
 
-fn first_four_lines<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
+/// The `$MeshFormat` section of a file, as used by [`msh_header`].
+pub fn first_four_lines<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
     use std::io::BufRead;
     // examine first 3-4 lines, instead of reading the whole file
     // because binary files aren't utf8
@@ -200,6 +394,16 @@ pub fn end_of_line(input: &str) -> IResult<&str, &str> {
     }
 }
 
+/// Byte-level version of [`end_of_line`].
+fn eol(input: &[u8]) -> IResult<&[u8], ()> {
+    if input.is_empty() {
+        Ok((input, ()))
+    } else {
+        let (input, _) = terminated(space0, line_ending)(input)?;
+        Ok((input, ()))
+    }
+}
+
 pub fn format_header(input: &str) -> IResult<&str, &str> {
     terminated(tag("$MeshFormat"), end_of_line)(input)
 }
@@ -209,85 +413,211 @@ pub fn format_footer(input: &str) -> IResult<&str, &str> {
 }
 
 pub fn msh2_ascii_header(input: &str) -> IResult<&str, MshVersion> {
-    let ((input, _)) = format_header(input)?;
-    let ((input, _)) = terminated(tag("2.2 0 8"), end_of_line)(input)?;
-    let ((input, _)) = format_footer(input)?;
+    let (input, _) = format_header(input)?;
+    let (input, _) = terminated(tag("2.2 0 8"), end_of_line)(input)?;
+    let (input, _) = format_footer(input)?;
     Ok((input, MshVersion::AsciiV22))
 }
 
 pub fn msh4_ascii_header(input: &str) -> IResult<&str, MshVersion> {
-    let ((input, _)) = format_header(input)?;
-    let ((input, _)) = terminated(tag("4.1 0 8"), end_of_line)(input)?;
-    let ((input, _)) = format_footer(input)?;
+    let (input, _) = format_header(input)?;
+    let (input, _) = terminated(tag("4.1 0 8"), end_of_line)(input)?;
+    let (input, _) = format_footer(input)?;
     Ok((input, MshVersion::AsciiV41))
 }
 
 pub fn msh_header(input: &[u8]) -> IResult<&[u8], MshVersion> {
+    in_section("$MeshFormat", header_fields(input)).map_err(|err| err.map(as_header_error))
+}
+
+/// Anything but an unknown endianness is reported as a bad header.
+fn as_header_error<I>(err: ParseError<I>) -> ParseError<I> {
+    match err.kind {
+        ParseErrorKind::Nom(_) => ParseError { kind: ParseErrorKind::Header, ..err },
+        _ => err,
+    }
+}
+
+fn header_fields(input: &[u8]) -> IResult<&[u8], MshVersion> {
     let (input, _) = terminated(tag("$MeshFormat"), line_ending)(input)?;
     let (input, version) = terminated(alt((tag("2.2"), tag("4.1"))), space1)(input)?;
     let (input, binary) = terminated(one_of("01"), space1)(input)?;
     let (mut input, _size_t) = terminated(char('8'), terminated(space0, line_ending))(input)?;
+    let mut big_endian = false;
     if binary == '1' {
+        // the integer 1, written in the file's byte order
         let (inner_input, endianness) = terminated(take(4usize), line_ending)(input)?;
         match endianness {
             [1, 0, 0, 0] => (),
-            [0, 0, 0, 1] => {
-                eprintln!("big-endian files are not supported");
-                return Err(Err::Error((input, nom::error::ErrorKind::Tag)));
-            },
-            _ => panic!("bad endianness byte sequence"),
+            [0, 0, 0, 1] => big_endian = true,
+            _ => {
+                let marker = [endianness[0], endianness[1], endianness[2], endianness[3]];
+                return fail(input, ParseErrorKind::Endianness(marker));
+            }
         }
         // update parser position in outer scope
         input = inner_input;
     }
     let (input, _) = terminated(tag("$EndMeshFormat"), line_ending)(input)?;
-    match (version, binary) {
-        (b"2.2", '0') => Ok((input, MshVersion::AsciiV22)),
-        (b"2.2", '1') => Ok((input, MshVersion::BinaryLeV22)),
-        (b"4.1", '0') => Ok((input, MshVersion::AsciiV41)),
-        (b"4.1", '1') => Ok((input, MshVersion::BinaryLeV41)),
-        _ => Err(Err::Error((input, nom::error::ErrorKind::Tag))),
+    match (version, binary, big_endian) {
+        (b"2.2", '0', _) => Ok((input, MshVersion::AsciiV22)),
+        (b"2.2", '1', false) => Ok((input, MshVersion::BinaryLeV22)),
+        (b"2.2", '1', true) => Ok((input, MshVersion::BinaryBeV22)),
+        (b"4.1", '0', _) => Ok((input, MshVersion::AsciiV41)),
+        (b"4.1", '1', false) => Ok((input, MshVersion::BinaryLeV41)),
+        (b"4.1", '1', true) => Ok((input, MshVersion::BinaryBeV41)),
+        _ => Err(Err::Error(ParseError::new(input, ParseErrorKind::Header))),
     }
 }
 
-fn parse_node_section_msh2(input: &str) -> IResult<&str, Vec<Node>> {
-    let (input, _) = terminated(tag("$Nodes"), end_of_line)(input)?;
-    let (input, num_nodes) = cut(terminated(parse_u64, end_of_line))(input)?;
-    let (input, (nodes, _)) = many_till(parse_node_msh2, terminated(tag("$EndNodes"), end_of_line))(input)?;
-    if num_nodes != nodes.len() as u64 {
-        // we don't really care if the number doesn't line up for msh2
-        eprintln!("warning: node header says {} nodes, but parsed {}", num_nodes, nodes.len());
-    }
-    Ok((input, nodes))
-}
-
-fn parse_unknown_section(input: &str) -> IResult<&str, ()> {
-    let (input, section_name) = delimited(char('$'), take_until("\n"), end_of_line)(input)?;
-    eprintln!("skipping unknown section ${}", section_name);
-    // fast-forward to ending line
-    let (input, _) = take_until("$End")(input)?;
-    // skip the ending line and continue
-    let (input, _) = delimited(char('$'), take_until("\n"), end_of_line)(input)?;
+fn parse_node_section_msh2<'a>(input: &'a str, visit: &mut Visit, diagnostics: &Diagnostics) -> IResult<&'a str, ()> {
+    let (count_line, _) = terminated(tag("$Nodes"), end_of_line)(input)?;
+    let (input, num_nodes) = cut(terminated(parse_u64, end_of_line))(count_line)?;
+    let (input, found) = match parallel_records(input, "$EndNodes", diagnostics, |i, _| parse_node_msh2(i)) {
+        Some((rest, chunks)) => {
+            let mut found = 0;
+            for (_, node) in chunks.into_iter().flatten() {
+                visit.node(node);
+                found += 1;
+            }
+            (rest, found)
+        }
+        None => {
+            let node = |input| {
+                let (rest, node) = parse_node_msh2(input)?;
+                visit.node(node);
+                Ok((rest, ()))
+            };
+            each_till(input, node, terminated(tag("$EndNodes"), end_of_line))?
+        }
+    };
+    if num_nodes != found {
+        let kind = DiagnosticKind::CountMismatch { expected: num_nodes, found };
+        diagnostics.warn(count_line, "$Nodes", kind)?;
+    }
     Ok((input, ()))
 }
 
+/// Like `many_till`, for items handed on as they're read instead of collected.
+/// Returns how many there were.
+fn each_till<'a, F, G, O>(mut input: &'a str, mut item: F, end: G) -> IResult<&'a str, u64>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, ()>,
+    G: Fn(&'a str) -> IResult<&'a str, O>,
+{
+    let mut found = 0;
+    loop {
+        if let Ok((rest, _)) = end(input) {
+            return Ok((rest, found));
+        }
+        let (rest, _) = item(input)?;
+        found += 1;
+        input = rest;
+    }
+}
+
+/// Records read from one chunk of a section, each with the input from its start.
+type Chunk<'a, T> = Vec<(&'a str, T)>;
+
+/// The records of a large section, read on several threads if enabled, see [`ReadOptions::parallel`].
+#[cfg(feature = "parallel")]
+fn parallel_records<'a, 'd, T, F>(
+    input: &'a str,
+    end: &'static str,
+    diagnostics: &Diagnostics<'d>,
+    record: F,
+) -> Option<(&'a str, Vec<Chunk<'a, T>>)>
+where
+    T: Send,
+    F: Fn(&'a str, &Diagnostics<'d>) -> IResult<&'a str, T> + Sync,
+{
+    parallel::records(input, end, diagnostics, record)
+}
+
+/// Sections are always parsed serially without the `parallel` feature.
+#[cfg(not(feature = "parallel"))]
+fn parallel_records<'a, 'd, T, F>(
+    _: &'a str,
+    _: &'static str,
+    _: &Diagnostics<'d>,
+    _: F,
+) -> Option<(&'a str, Vec<Chunk<'a, T>>)>
+where
+    F: Fn(&'a str, &Diagnostics<'d>) -> IResult<&'a str, T>,
+{
+    None
+}
+
+/// Like `cut`, for parsers that hand on what they read and so can't be `Fn`.
+fn cut_result<I, O>(result: IResult<I, O>) -> IResult<I, O> {
+    result.map_err(|err| match err {
+        Err::Error(err) => Err::Failure(err),
+        err => err,
+    })
+}
+
+/// Like `count`, reusing the allocation of `out`.
+fn count_into<I, O, F>(mut input: I, parser: F, n: usize, out: &mut Vec<O>) -> IResult<I, ()>
+where
+    F: Fn(I) -> IResult<I, O>,
+{
+    out.clear();
+    for _ in 0..n {
+        let (rest, item) = parser(input)?;
+        out.push(item);
+        input = rest;
+    }
+    Ok((input, ()))
+}
+
+/// Any other section's opening line, except the start of another mesh.
+fn unknown_section_header(input: &[u8]) -> IResult<&[u8], &str> {
+    let name = take_while1(|c: u8| c.is_ascii_alphanumeric() || c == b'_');
+    let (rest, name) = delimited(char('$'), map_res(name, std::str::from_utf8), eol)(input)?;
+    if name == "MeshFormat" || name.starts_with("End") {
+        return Err(Err::Error(ParseError::new(input, ParseErrorKind::Nom(nom::error::ErrorKind::Tag))));
+    }
+    Ok((rest, name))
+}
+
+/// Keeps a section's contents as they are, binary data included.
+fn parse_unknown_section<'a>(input: &'a [u8], diagnostics: &Diagnostics) -> IResult<&'a [u8], RawSection> {
+    let (body, name) = unknown_section_header(input)?;
+    diagnostics.info(input, None, DiagnosticKind::UnknownSection { name: name.to_string() });
+    let end = format!("$End{}", name);
+    // fast-forward to the closing line, skipping look-alikes inside lines
+    let mut len = 0;
+    loop {
+        let (_, skipped) = cut(take_until(end.as_str()))(&body[len..])?;
+        len += skipped.len();
+        let line_start = len == 0 || body[len - 1] == b'\n';
+        if line_start && eol(&body[len + end.len()..]).is_ok() {
+            break;
+        }
+        len += end.len();
+    }
+    let (contents, rest) = body.split_at(len);
+    let (rest, _) = terminated(tag(end.as_str()), eol)(rest)?;
+    Ok((rest, RawSection { name: name.to_string(), contents: contents.to_vec() }))
+}
+
 fn parse_node_msh2(input: &str) -> IResult<&str, Node> {
     do_parse!(input,
         tag: parse_u64 >> sp >>
         x: double >> sp >>
         y: double >> sp >>
         z: double >> sp >>
-        ( Node { tag, x, y, z } )
+        ( Node { tag, x, y, z, entity: None, parametric: None } )
     )
 }
 
-fn parse_physical_groups_msh2(input: &str) -> IResult<&str, Vec<PhysicalGroup>> {
-    let (input, _) = terminated(tag("$PhysicalNames"), end_of_line)(input)?;
-    let (input, num_groups) = cut(terminated(parse_u64, end_of_line))(input)?;
+fn parse_physical_groups_msh2<'a>(input: &'a str, diagnostics: &Diagnostics) -> IResult<&'a str, Vec<PhysicalGroup>> {
+    let (count_line, _) = terminated(tag("$PhysicalNames"), end_of_line)(input)?;
+    let (input, num_groups) = cut(terminated(parse_u64, end_of_line))(count_line)?;
     let (input, (groups, _)) = many_till(parse_physical_group_msh2, terminated(tag("$EndPhysicalNames"), end_of_line))(input)?;
     if num_groups != groups.len() as u64 {
-        // we don't really care if the number doesn't line up for msh2
-        eprintln!("warning: header says {} physical groups, but read {}", num_groups, groups.len());
+        let kind = DiagnosticKind::CountMismatch { expected: num_groups, found: groups.len() as u64 };
+        diagnostics.warn(count_line, "$PhysicalNames", kind)?;
     }
     Ok((input, groups))
 }
@@ -302,6 +632,33 @@ fn parse_physical_group_msh2(input: &str) -> IResult<&str, PhysicalGroup> {
     Ok((input, PhysicalGroup { dim, tag, name: name.to_string() }))
 }
 
+/// Periodic links are text in every 2.2 storage.
+fn parse_periodic_msh2(input: &str) -> IResult<&str, Vec<PeriodicLink>> {
+    let (input, _) = terminated(tag("$Periodic"), end_of_line)(input)?;
+    let (input, num_links) = cut(terminated(parse_u64, end_of_line))(input)?;
+    let (input, links) = cut(count(parse_periodic_link_msh2, num_links as usize))(input)?;
+    let (input, _) = cut(terminated(tag("$EndPeriodic"), end_of_line))(input)?;
+    Ok((input, links))
+}
+
+fn parse_periodic_link_msh2(input: &str) -> IResult<&str, PeriodicLink> {
+    let (input, dim) = terminated(parse_dimension, sp)(input)?;
+    let (input, entity) = terminated(parse_u64, sp)(input)?;
+    let (input, master) = terminated(parse_u64, end_of_line)(input)?;
+    let (input, affine) = opt(delimited(tag("Affine"), count(preceded(sp, double), 16), end_of_line))(input)?;
+    let (input, num_nodes) = terminated(parse_u64, end_of_line)(input)?;
+    let node_pair = terminated(pair(terminated(parse_u64, sp), parse_u64), end_of_line);
+    let (input, nodes) = count(node_pair, num_nodes as usize)(input)?;
+    Ok((input, PeriodicLink { dim, entity, master, affine: affine.map(|values| affine_matrix(&values)), nodes }))
+}
+
+/// Sixteen affine transform values, as read.
+fn affine_matrix(values: &[f64]) -> [f64; 16] {
+    let mut matrix = [0.0; 16];
+    matrix.copy_from_slice(values);
+    matrix
+}
+
 fn parse_dimension(input: &str) -> IResult<&str, Dim> {
     let (input, dim) = one_of("0123")(input)?;
     Ok((input, Dim::from_u8_unchecked(u8::from_str(&dim.to_string()).unwrap())))
@@ -315,57 +672,168 @@ fn parse_u64_sp(input: &str) ->  IResult<&str, u64> {
     terminated(map_res(digit1, u64::from_str), sp)(input)
 }
 
-fn parse_elements_section_msh2(input: &str) -> IResult<&str, Vec<MeshElt>> {
-    let (input, _) = terminated(tag("$Elements"), end_of_line)(input)?;
-    let (input, num_elts) = cut(terminated(parse_u64, end_of_line))(input)?;
-    let (input, (elts, _)) = many_till(parse_element_msh2, terminated(tag("$EndElements"), end_of_line))(input)?;
-    if num_elts != elts.len() as u64 {
-        // we don't really care if the number doesn't line up for msh2
-        eprintln!("warning: header says {} elements, but read {}", num_elts, elts.len());
+fn parse_i64_sp(input: &str) -> IResult<&str, i64> {
+    let signed = recognize(pair(opt(char('-')), digit1));
+    terminated(map_res(signed, i64::from_str), sp)(input)
+}
+
+fn parse_elements_section_msh2<'a>(
+    input: &'a str,
+    visit: &mut Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a str, ()> {
+    let (count_line, _) = terminated(tag("$Elements"), end_of_line)(input)?;
+    let (input, num_elts) = cut(terminated(parse_u64, end_of_line))(count_line)?;
+    let owned_element = |input, diagnostics: &Diagnostics| {
+        let mut elt = MeshElt::new(0, MeshShape::Point, Vec::new());
+        let (rest, _) = parse_element_msh2(input, &mut elt, diagnostics)?;
+        Ok((rest, elt))
+    };
+    let (input, found) = match parallel_records(input, "$EndElements", diagnostics, owned_element) {
+        Some((rest, chunks)) => {
+            let mut found = 0;
+            for (start, elt) in chunks.iter().flatten() {
+                check_nodes(*start, elt, visit, diagnostics)?;
+                visit.element(elt);
+                found += 1;
+            }
+            (rest, found)
+        }
+        None => {
+            // one element is reused for every record
+            let mut elt = MeshElt::new(0, MeshShape::Point, Vec::new());
+            let element = |input: &'a str| {
+                let (rest, _) = parse_element_msh2(input, &mut elt, diagnostics)?;
+                check_nodes(input, &elt, visit, diagnostics)?;
+                visit.element(&elt);
+                Ok((rest, ()))
+            };
+            each_till(input, element, terminated(tag("$EndElements"), end_of_line))?
+        }
+    };
+    if num_elts != found {
+        let kind = DiagnosticKind::CountMismatch { expected: num_elts, found };
+        diagnostics.warn(count_line, "$Elements", kind)?;
     }
-    Ok((input, elts))
+    Ok((input, ()))
 }
 
-fn parse_element_msh2(input: &str) -> IResult<&str, MeshElt> {
+/// Reads an element record into `elt`.
+fn parse_element_msh2<'a>(input: &'a str, elt: &mut MeshElt, diagnostics: &Diagnostics) -> IResult<&'a str, ()> {
     let (input, tag) = terminated(parse_u64, sp)(input)?;
-    let (input, label) = terminated(digit1, sp)(input)?;
-    let elt_type = match MeshShape::from_gmsh_label(label) {
+    let (rest, label) = terminated(digit1, sp)(input)?;
+    // polygons and polyhedra can't be read, since records don't give their node count
+    let (elt_type, num_nodes) = match MeshShape::from_gmsh_label(label).and_then(sized) {
         Some(ty) => ty,
-        None => panic!(format!("unknown mesh element type: {}", label)),
+        None => return fail(input, ParseErrorKind::ElementType(label.parse().unwrap_or(i64::MAX))),
     };
+    let input = rest;
 
-    let (input, elt_info) = parse_elt_info(input)?;
-    let (input, nodes) = count(parse_u64_sp, elt_type.num_nodes() as usize)(input)?;
+    let (input, elt_info) = parse_elt_info(input, diagnostics)?;
+    let (input, _) = count_into(input, parse_u64_sp, num_nodes, &mut elt.nodes)?;
+    elt_info.fill(tag, elt_type, elt);
+    Ok((input, ()))
+}
 
-    let uint_to_tag = |uint| if uint != 0 { Some(uint) } else { None };
-    Ok((input, MeshElt {
-        tag,
-        ty: elt_type,
-        nodes,
-        // multiple physical groups are handled by duplicate shapes
-        physical_group: uint_to_tag(elt_info.physical_group),
-        geometry: uint_to_tag(elt_info.geometry),
-    }))
+/// Warns about the first node of `elt` that wasn't read before it, failing in strict mode.
+fn check_nodes<I: AsBytes>(
+    input: I,
+    elt: &MeshElt,
+    visit: &Visit,
+    diagnostics: &Diagnostics,
+) -> Result<(), Err<ParseError<I>>> {
+    match elt.nodes.iter().find(|&&node| !visit.has_node(node)) {
+        Some(&node) => {
+            let kind = DiagnosticKind::DanglingNode { element: elt.tag, node };
+            diagnostics.warn(input, "$Elements", kind)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Pairs an element type with its node count, if fixed.
+fn sized(ty: MeshShape) -> Option<(MeshShape, usize)> {
+    ty.num_nodes().map(|num_nodes| (ty, num_nodes))
 }
 
+/// The tags of a 2.2 element record, following
+/// https://gitlab.onelab.info/gmsh/gmsh/-/blob/master/Geo/GModelIO_MSH2.cpp#L370
+///
+/// After the physical group and geometry come the number of partitions and the
+/// partitions themselves, negative for ghost copies. A single tag after those
+/// is the parent element, two are the domains of an interface element.
 struct EltInfo {
-    pub physical_group: Tag,
-    pub geometry: Tag,
-    // lots of options here:
-    // https://gitlab.onelab.info/gmsh/gmsh/-/blob/master/Geo/GModelIO_MSH2.cpp#L370
-    //pub mesh_partition: Option<Tag>,
-    //pub ghost_elements: Option<Vec<Tag>>,
-    //pub domain: Option<(Tag, Tag)>,
-    //pub parent_elt: Option<Tag>,
+    physical_group: Tag,
+    geometry: Tag,
+    partitions: Vec<Tag>,
+    ghost_partitions: Vec<Tag>,
+    parent: Tag,
+    domain: Option<(Tag, Tag)>,
+    /// Number of tags that don't fit the layout above, read as zero or skipped.
+    num_ignored: usize,
+}
+
+fn parse_elt_info<'a>(input: &'a str, diagnostics: &Diagnostics) -> IResult<&'a str, EltInfo> {
+    let (tags_start, num_info) = parse_u64_sp(input)?;
+    let (input, elt_info) = count(parse_i64_sp, num_info as usize)(tags_start)?;
+    let elt_info = EltInfo::from_tags(&elt_info);
+    if elt_info.num_ignored > 0 {
+        diagnostics.warn_once(tags_start, "$Elements", DiagnosticKind::IgnoredElementTags)?;
+    }
+    Ok((input, elt_info))
 }
 
-fn parse_elt_info(input: &str) -> IResult<&str, EltInfo> {
-    let (input, num_info) = parse_u64_sp(input)?;
-    if num_info > 2 {
-        eprintln!("warning: only reading physical group and geometry information and skipping partitions, ghost elements...");
+impl EltInfo {
+    /// Missing tags are treated as zero, i.e. no group, geometry or partitions.
+    fn from_tags(tags: &[i64]) -> EltInfo {
+        let uint = |tag: i64| Tag::try_from(tag).unwrap_or(0);
+        let at = |i: usize| tags.get(i).copied().map_or(0, uint);
+        let negative = |tags: &[i64]| tags.iter().filter(|&&tag| tag < 0).count();
+        // group, geometry and partition count are never negative
+        let mut num_ignored = negative(&tags[..tags.len().min(3)]);
+        // the third tag counts the partitions listed right after it
+        let num_partitions = match tags.len() {
+            0..=2 => 0,
+            len => (at(2) as usize).min(len - 3),
+        };
+        if tags.len() >= 3 && at(2) as usize > num_partitions {
+            num_ignored += 1;
+        }
+        let listed = tags.get(3..3 + num_partitions).unwrap_or(&[]);
+        num_ignored += listed.iter().filter(|&&partition| partition == 0).count();
+        let rest = tags.get(3 + num_partitions..).unwrap_or(&[]);
+        let (parent, domain) = match *rest {
+            [] => (0, None),
+            [parent] => (uint(parent), None),
+            [first, second] => (0, Some((uint(first), uint(second)))),
+            _ => (0, None),
+        };
+        num_ignored += if rest.len() > 2 { rest.len() } else { negative(rest) };
+        EltInfo {
+            physical_group: at(0),
+            geometry: at(1),
+            partitions: listed.iter().filter(|&&partition| partition > 0).map(|&partition| uint(partition)).collect(),
+            ghost_partitions: listed.iter().filter(|&&partition| partition < 0).map(|&partition| uint(-partition)).collect(),
+            parent,
+            domain,
+            num_ignored,
+        }
+    }
+
+    /// Sets everything but the nodes of `elt`.
+    fn fill(self, tag: Tag, ty: MeshShape, elt: &mut MeshElt) {
+        let uint_to_tag = |uint| if uint != 0 { Some(uint) } else { None };
+        elt.tag = tag;
+        elt.ty = ty;
+        // multiple physical groups are handled by duplicate shapes, see `Msh::merge_duplicate_elements`
+        elt.physical_groups.clear();
+        elt.physical_groups.extend(uint_to_tag(self.physical_group));
+        elt.geometry = uint_to_tag(self.geometry);
+        elt.partitions = self.partitions;
+        elt.ghost_partitions = self.ghost_partitions;
+        elt.parent = uint_to_tag(self.parent);
+        elt.domain = self.domain;
     }
-    let (input, elt_info) = count(parse_u64_sp, num_info as usize)(input)?;
-    Ok((input, EltInfo { physical_group: elt_info[0], geometry: elt_info[1] }))
 }
 
 #[cfg(test)]
@@ -374,6 +842,26 @@ mod tests {
     use std::path::PathBuf;
     use insta::{assert_debug_snapshot, assert_display_snapshot};
 
+    /// Runs a parser without collecting its diagnostics.
+    fn lenient<'a, O>(
+        parser: impl Fn(&'a str, &Diagnostics) -> IResult<&'a str, O>,
+        input: &'a str,
+    ) -> IResult<&'a str, O> {
+        parser(input, &Diagnostics::lenient(input.as_bytes()))
+    }
+
+    fn element_msh2(input: &str) -> IResult<&str, MeshElt> {
+        let mut elt = MeshElt::new(0, MeshShape::Point, Vec::new());
+        let (rest, _) = parse_element_msh2(input, &mut elt, &Diagnostics::lenient(input.as_bytes()))?;
+        Ok((rest, elt))
+    }
+
+    fn node_section_msh2(input: &str) -> IResult<&str, Vec<Node>> {
+        let mut msh = Msh::new();
+        let (rest, _) = parse_node_section_msh2(input, &mut Visit::new(&mut msh), &Diagnostics::lenient(input.as_bytes()))?;
+        Ok((rest, msh.nodes))
+    }
+
     #[test]
     fn trailing_spaces() {
         let inp = "101 0 1 100.0      \t\n";
@@ -387,20 +875,48 @@ mod msh2 {
     #[test]
     fn msh2_ascii() {
         let msh = std::fs::read_to_string("props/v2/basic.msh").unwrap();
-        assert_debug_snapshot!(parse_msh2_ascii(&msh).unwrap().1);
+        assert_debug_snapshot!(lenient(parse_msh2_ascii, &msh).unwrap().1);
     }
 
     #[test]
     fn concatenated_mesh_files() {
         // add basic mesh to itself and make sure the copies match
-        let mut msh = std::fs::read_to_string("props/v2/basic.msh").unwrap();
+        let msh = std::fs::read_to_string("props/v2/basic.msh").unwrap();
         let msh = msh.clone() + &msh;
         assert_debug_snapshot!(parse_msh_file(&msh).unwrap());
     }
 
+    #[test]
+    fn from_file() {
+        let ascii = Msh::from_file("props/v2/tetra.msh").unwrap();
+        assert_eq!(ascii.elts.len(), 68);
+        assert_eq!(Msh::from_file("props/v2/bin-tetra.msh").unwrap(), ascii);
+    }
+
+    #[test]
+    fn read_concatenated() {
+        let twice = std::fs::read("props/v2/basic-twice.msh").unwrap();
+        let meshes = Msh::read_all(twice.as_slice()).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0], meshes[1]);
+        let merged = Msh::read(twice.as_slice()).unwrap();
+        assert_eq!(merged.nodes.len(), 2 * meshes[0].nodes.len());
+        assert_eq!(merged.elts.len(), 2 * meshes[0].elts.len());
+    }
+
+    #[test]
+    fn read_bad_header() {
+        assert!(Msh::from_file("props/v2/bad-header.msh").is_err());
+        assert!(msh_version("props/v2/bad-header.msh").is_err());
+    }
+
     #[test]
     fn unknown_section() {
-        assert_debug_snapshot!(parse_unknown_section("$Comments\nhi there\nfinished in 10.2seconds\n$EndComments\n").unwrap().1);
+        let input = b"$Comments\nhi there\nfinished in 10.2seconds\n$EndComments\n";
+        let (rest, section) = parse_unknown_section(input, &Diagnostics::lenient(input)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(section.name, "Comments");
+        assert_eq!(section.contents, b"hi there\nfinished in 10.2seconds\n");
     }
 
     #[test]
@@ -411,34 +927,142 @@ mod msh2 {
              1 15 2 0 0 5\n\
              500 1 2 1 2 30 31\n\
              $EndElements";
-        let mut msh_input = input;
-        let res = parse_msh2_ascii(msh_input);
+        let msh_input = input;
+        let res = lenient(parse_msh2_ascii, msh_input);
         assert!(res.is_err());
     }
 
     #[test]
     fn node_elt() {
-        assert_debug_snapshot!(parse_element_msh2("1 15 2 0 0 5\n").unwrap().1);
+        assert_debug_snapshot!(element_msh2("1 15 2 0 0 5\n").unwrap().1);
     }
 
     #[test]
     fn line_elt() {
-        assert_debug_snapshot!(parse_element_msh2("500 1 2 1 2 30 31\n").unwrap().1);
+        assert_debug_snapshot!(element_msh2("500 1 2 1 2 30 31\n").unwrap().1);
     }
 
     #[test]
     fn tri_elt() {
-        assert_debug_snapshot!(parse_element_msh2("10 2 2 5 1 1 2 3\n").unwrap().1);
+        assert_debug_snapshot!(element_msh2("10 2 2 5 1 1 2 3\n").unwrap().1);
     }
 
     #[test]
     fn tetra_elt() {
-        assert_debug_snapshot!(parse_element_msh2("41 4 2 0 1 1 2 3 4\n").unwrap().1);
+        assert_debug_snapshot!(element_msh2("41 4 2 0 1 1 2 3 4\n").unwrap().1);
+    }
+
+    #[test]
+    fn hex_elt() {
+        let (_, elt) = element_msh2("3 5 2 0 1 1 2 3 4 5 6 7 8\n").unwrap();
+        assert_eq!((elt.ty, elt.nodes.len()), (MeshShape::Hexahedron, 8));
+    }
+
+    #[test]
+    fn second_order_tri_elt() {
+        assert_debug_snapshot!(element_msh2("7 9 2 0 1 1 2 3 4 5 6\n").unwrap().1);
+    }
+
+    #[test]
+    fn unknown_elt_type() {
+        assert!(element_msh2("7 999 2 0 1 1 2\n").is_err());
+        // polygon records don't say how many nodes they have
+        assert!(element_msh2("7 34 2 0 1 1 2 3\n").is_err());
     }
 
     #[test]
     fn elt_extra_fields() {
-        assert_debug_snapshot!(parse_element_msh2("41 4 5 0 1 1 2 3 41 42 43 44\n").unwrap().1);
+        assert_debug_snapshot!(element_msh2("41 4 5 0 1 1 2 3 41 42 43 44\n").unwrap().1);
+    }
+
+    #[test]
+    fn partitioned_elt() {
+        // owned by partition 2, ghosted in 4 and 5, refined from element 9
+        let elt = element_msh2("1 2 7 3 1 3 2 -4 -5 9 1 2 3\n").unwrap().1;
+        assert_eq!((elt.physical_groups, elt.geometry), (vec![3], Some(1)));
+        assert_eq!(elt.partitions, vec![2]);
+        assert_eq!(elt.ghost_partitions, vec![4, 5]);
+        assert_eq!((elt.parent, elt.domain), (Some(9), None));
+        assert_eq!(elt.nodes, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn interface_elt_domain() {
+        let elt = element_msh2("1 1 5 0 1 0 3 4 1 2\n").unwrap().1;
+        assert!(elt.partitions.is_empty());
+        assert_eq!((elt.parent, elt.domain), (None, Some((3, 4))));
+    }
+
+    #[test]
+    fn few_elt_tags() {
+        for input in &["1 1 0 1 2\n", "1 1 1 5 1 2\n"] {
+            let elt = element_msh2(input).unwrap().1;
+            assert_eq!(elt.nodes, vec![1, 2]);
+            assert_eq!(elt.geometry, None);
+        }
+    }
+
+    #[test]
+    fn partition_count_without_partitions() {
+        let msh = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n2\n1 0 0 0\n2 1 0 0\n$EndNodes\n\
+                   $Elements\n1\n1 1 3 4 1 0 1 2\n$EndElements\n";
+        let (meshes, diagnostics) = parse_msh_file_with(msh, &ReadOptions::default()).unwrap();
+        assert!(diagnostics.is_empty());
+        let elt = &meshes[0].elts[0];
+        assert_eq!((&elt.physical_groups, elt.geometry), (&vec![4], Some(1)));
+        assert!(elt.partitions.is_empty());
+        assert_eq!(elt.parent, None);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let msh = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n3\n1 0 0 0\n2 1 0 0\n$EndNodes\n\
+                   $Elements\n2\n1 1 3 0 1 2 1 2\n2 15 9 0 1 1 3 0 0 0 7 8 1\n$EndElements\n";
+        let (_, diagnostics) = parse_msh_file_with(msh, &ReadOptions::default()).unwrap();
+        let warnings: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+        assert_eq!(warnings, vec![
+            "warning: expected 3 entries but found 2 at line 5 (byte 42) in $Nodes",
+            "warning: skipped unrecognized element tags at line 11 (byte 88) in $Elements",
+        ]);
+    }
+
+    #[test]
+    fn partitions_past_tags() {
+        let msh = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n1\n1 0 0 0\n$EndNodes\n\
+                   $Elements\n1\n1 15 4 4 1 3 2 1\n$EndElements\n";
+        let (meshes, diagnostics) = parse_msh_file_with(msh, &ReadOptions::default()).unwrap();
+        assert_eq!(meshes[0].elts[0].partitions, vec![2]);
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec![DiagnosticKind::IgnoredElementTags]);
+        let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+        assert!(parse_msh_file_with(msh, &strict).is_err());
+    }
+
+    #[test]
+    fn negative_group_and_geometry() {
+        for tags in &["-4 1", "4 -1"] {
+            let msh = format!(
+                "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n1\n1 0 0 0\n$EndNodes\n\
+                 $Elements\n1\n1 15 2 {} 1\n$EndElements\n",
+                tags
+            );
+            let (_, diagnostics) = parse_msh_file_with(&msh, &ReadOptions::default()).unwrap();
+            let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+            assert_eq!(kinds, vec![DiagnosticKind::IgnoredElementTags], "{}", tags);
+            let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+            assert!(parse_msh_file_with(&msh, &strict).is_err(), "{}", tags);
+        }
+    }
+
+    #[test]
+    fn strict_count_mismatch() {
+        let msh = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n3\n1 0 0 0\n2 1 0 0\n$EndNodes\n";
+        assert!(parse_msh_file(msh).is_ok());
+        let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+        match parse_msh_file_with(msh, &strict) {
+            Err(MshError::Count { expected: 3, found: 2, location }) => assert_eq!(location.line, 5),
+            other => panic!("expected a count error, got {:?}", other),
+        }
     }
 
     #[test]
@@ -455,8 +1079,8 @@ mod msh2 {
 
     #[test]
     fn pgroups_section() {
-        assert_debug_snapshot!(parse_physical_groups_msh2(
-            &r#"$PhysicalNames
+        assert_debug_snapshot!(lenient(parse_physical_groups_msh2, 
+            r#"$PhysicalNames
 4
 0 1 "a point"
 0 2 "hi"
@@ -467,7 +1091,7 @@ $EndPhysicalNames"#).unwrap().1);
 
     #[test]
     fn bad_physical_group_dimension() {
-        let res = parse_physical_group_msh2(&r#"4 1 "Water cube"#);
+        let res = parse_physical_group_msh2(r#"4 1 "Water cube"#);
         assert!(res.is_err());
         if let Err(trace) = res {
             assert_display_snapshot!(trace);
@@ -477,19 +1101,19 @@ $EndPhysicalNames"#).unwrap().1);
     #[test]
     fn some_nodes() {
         let i = "$Nodes\n3\n1 0. 0. 1.\n2 1. 1 1\n100 1 1 1\n$EndNodes\n";
-        assert_debug_snapshot!(parse_node_section_msh2(i).unwrap().1);
+        assert_debug_snapshot!(node_section_msh2(i).unwrap().1);
     }
 
     #[test]
     fn nodes_len_mismatch() {
         let inp = "$Nodes\n0\n1 0. 0. 1.\n2 1. 1 1\n100 1 1 1\n$EndNodes\n";
-        assert_debug_snapshot!(parse_node_section_msh2(inp).unwrap().1);
+        assert_debug_snapshot!(node_section_msh2(inp).unwrap().1);
     }
 
     #[test]
     fn empty_nodes() {
         let inp = "$Nodes\n0\n$EndNodes\n";
-        assert_debug_snapshot!(parse_node_section_msh2(inp).unwrap().1);
+        assert_debug_snapshot!(node_section_msh2(inp).unwrap().1);
     }
 
     #[test]
@@ -497,22 +1121,38 @@ $EndPhysicalNames"#).unwrap().1);
         let header = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n";
         match msh_header(header.as_bytes()) {
             Ok((_, MshVersion::AsciiV22)) => (),
-            _else => {std::dbg!(_else); panic!("bad mesh header")},
+            other => panic!("bad mesh header: {:?}", other),
         };
         let header = "$MeshFormat\n2.2 1 8\n\u{1}\u{0}\u{0}\u{0}\n$EndMeshFormat\n";
         match msh_header(header.as_bytes()) {
             Ok((_, MshVersion::BinaryLeV22)) => (),
-            _else => {std::dbg!(_else); panic!("bad mesh header")},
+            other => panic!("bad mesh header: {:?}", other),
         };
     }
 
+    #[test]
+    fn big_endian_header() {
+        let header = b"$MeshFormat\n2.2 1 8\n\0\0\0\x01\n$EndMeshFormat\n";
+        assert_eq!(parse_header(header).unwrap(), MshVersion::BinaryBeV22);
+    }
+
+    #[test]
+    fn unknown_endianness() {
+        let header = b"$MeshFormat\n4.1 1 8\n\0\x01\0\0\n$EndMeshFormat\n";
+        match parse_header(header) {
+            Err(MshError::Endianness { marker }) => assert_eq!(marker, [0, 1, 0, 0]),
+            other => panic!("expected an endianness error, got {:?}", other),
+        }
+        assert!(Msh::read(&header[..]).is_err());
+    }
+
     #[test]
     fn msh2_ascii_header() {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("props");
         path.push("v2");
         path.push("empty.msh");
-        assert_debug_snapshot!(msh_header(&first_four_lines(path).unwrap().as_bytes()).unwrap());
+        assert_debug_snapshot!(msh_header(first_four_lines(path).unwrap().as_bytes()).unwrap());
     }
 
     #[test]
@@ -521,7 +1161,7 @@ $EndPhysicalNames"#).unwrap().1);
         path.push("props");
         path.push("v2");
         path.push("unix-empty.msh");
-        assert_debug_snapshot!(msh_header(&first_four_lines(path).unwrap().as_bytes()).unwrap());
+        assert_debug_snapshot!(msh_header(first_four_lines(path).unwrap().as_bytes()).unwrap());
     }
 
     #[test]
@@ -530,7 +1170,7 @@ $EndPhysicalNames"#).unwrap().1);
         path.push("props");
         path.push("v2");
         path.push("empty-bin.msh");
-        assert_debug_snapshot!(msh_header(&first_four_lines(path).unwrap().as_bytes()).unwrap());
+        assert_debug_snapshot!(msh_header(first_four_lines(path).unwrap().as_bytes()).unwrap());
     }
 
     #[test]
@@ -540,24 +1180,120 @@ $EndPhysicalNames"#).unwrap().1);
         path.push("v2");
         path.push("bad-header.msh");
         let header_str = first_four_lines(path).unwrap();
-        let res = msh_header(&header_str.as_bytes());
+        let res = parse_header(header_str.as_bytes());
         assert!(res.is_err());
-        if let Err(trace) = res {
-            assert_display_snapshot!(trace);
+        if let Err(err) = res {
+            assert_display_snapshot!(err);
+        }
+    }
+
+    #[test]
+    fn error_location() {
+        let msh = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n1\n1 0 0 0\n$EndNodes\n\
+                   $Elements\n2\n1 15 2 0 0 1\n2 999 2 0 0 1\n$EndElements\n";
+        let err = parse_msh_file(msh).unwrap_err();
+        assert_eq!(err.location(), Some(&Location { section: Some("$Elements"), line: 11, offset: 89 }));
+        assert_display_snapshot!(err);
+    }
+
+    #[test]
+    fn dangling_node() {
+        let msh = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n1\n1 0 0 0\n$EndNodes\n\
+                   $Elements\n1\n1 1 2 0 0 1 2\n$EndElements\n";
+        // nodes are only checked when asked to
+        assert_eq!(parse_msh_file_with(msh, &ReadOptions::default()).unwrap().1, vec![]);
+        let check = ReadOptions { check_nodes: true, ..ReadOptions::default() };
+        let (read, diagnostics) = parse_msh_file_with(msh, &check).unwrap();
+        assert_eq!(read[0].elts.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::DanglingNode { element: 1, node: 2 });
+        assert_eq!(diagnostics[0].location.line, 10);
+        let mut reader = MshReader::with_options(msh.as_bytes(), &check);
+        assert_eq!(reader.by_ref().count(), 1);
+        assert_eq!(reader.into_diagnostics(), diagnostics);
+        let strict = ReadOptions { strict: true, ..check };
+        match parse_msh_file_with(msh, &strict) {
+            Err(MshError::DanglingNode { element: 1, node: 2, location }) => assert_eq!(location.line, 10),
+            other => panic!("expected a dangling node error, got {:?}", other),
         }
     }
+
+    #[test]
+    fn diagnostic_lines() {
+        // the count is checked last, so it's located before the nodes found
+        let msh = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n0\n$EndNodes\n\
+                   $Elements\n3\n1 15 2 0 0 1\n\n2 15 2 0 0 2\n$EndElements\n";
+        let check = ReadOptions { check_nodes: true, ..ReadOptions::default() };
+        let lines: Vec<_> = parse_msh_file_with(msh, &check).unwrap().1.iter().map(|d| d.location.line).collect();
+        assert_eq!(lines, vec![9, 11, 8]);
+    }
 }
 
 mod msh4 {
     use super::*;
 
+    #[test]
+    fn msh4_version() {
+        assert_eq!(msh_version("props/v4/square.msh").unwrap(), MshVersion::AsciiV41);
+        assert_eq!(msh_version("props/v4/square-bin.msh").unwrap(), MshVersion::BinaryLeV41);
+    }
+
+    #[test]
+    fn binary_from_file_matches_ascii() {
+        let ascii = Msh::from_file("props/v4/square.msh").unwrap();
+        assert_eq!(Msh::from_file("props/v4/square-bin.msh").unwrap(), ascii);
+    }
+
+    #[test]
+    fn concatenated_parametrizations() {
+        let once = std::fs::read("props/v4/parametric.msh").unwrap();
+        let twice = [once.as_slice(), once.as_slice()].concat();
+        let single = Msh::read(once.as_slice()).unwrap();
+        let meshes = Msh::read_all(twice.as_slice()).unwrap();
+        assert_eq!(meshes, vec![single.clone(), single.clone()]);
+        let merged = Msh::read(twice.as_slice()).unwrap();
+        let curves = &single.parametrizations.curves;
+        assert_eq!(merged.parametrizations.curves, [curves.as_slice(), curves.as_slice()].concat());
+        let surfaces = &single.parametrizations.surfaces;
+        assert_eq!(merged.parametrizations.surfaces, [surfaces.as_slice(), surfaces.as_slice()].concat());
+    }
+
+    #[test]
+    fn count_mismatch() {
+        let msh = std::fs::read_to_string("props/v4/square.msh").unwrap().replace("$Nodes\n6 5", "$Nodes\n6 6");
+        let (meshes, diagnostics) = parse_msh_file_with(&msh, &ReadOptions::default()).unwrap();
+        assert_eq!(meshes[0].nodes.len(), 5);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::CountMismatch { expected: 6, found: 5 });
+        assert_eq!(diagnostics[0].location.section, Some("$Nodes"));
+        let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+        match parse_msh_file_with(&msh, &strict) {
+            Err(MshError::Count { expected: 6, found: 5, location }) => assert_eq!(location.section, Some("$Nodes")),
+            other => panic!("expected a count error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let bin = std::fs::read("props/v4/square-bin.msh").unwrap();
+        let start = bin.windows(7).position(|w| w == b"$Nodes\n").unwrap();
+        match parse_msh_file(&bin[..start + 100]) {
+            Err(MshError::Truncated { location }) => {
+                assert_eq!(location.section, Some("$Nodes"));
+                // the first value that doesn't fit
+                assert!(start < location.offset && location.offset <= start + 100);
+            }
+            other => panic!("expected a truncated block error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn msh4_ascii_header() {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("props");
         path.push("v4");
         path.push("empty.msh");
-        assert_debug_snapshot!(msh_header(&first_four_lines(path).unwrap().as_bytes()).unwrap());
+        assert_debug_snapshot!(msh_header(first_four_lines(path).unwrap().as_bytes()).unwrap());
     }
 
     #[test]
@@ -566,7 +1302,7 @@ mod msh4 {
         path.push("props");
         path.push("v4");
         path.push("empty-bin.msh");
-        assert_debug_snapshot!(msh_header(&first_four_lines(path).unwrap().as_bytes()).unwrap());
+        assert_debug_snapshot!(msh_header(first_four_lines(path).unwrap().as_bytes()).unwrap());
     }
 }
 }
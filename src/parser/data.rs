@@ -0,0 +1,163 @@
+//! `$NodeData`, `$ElementData` and `$ElementNodeData` sections.
+//!
+//! Their layout is the same in every version: tags are ASCII lines, followed
+//! by one record per entry in the file's encoding.
+use super::*;
+use super::encoding::{Ascii, Encoding};
+use crate::data::{DataEntry, DataKind, DataStep, InterpolationScheme, Matrix, TopologyInterpolation};
+
+use nom::bytes::complete::take_till;
+use nom::character::complete::multispace0;
+use nom::sequence::preceded;
+
+use std::convert::TryFrom;
+
+/// A data section's opening line.
+pub(crate) fn data_header(input: &[u8]) -> IResult<&[u8], DataKind> {
+    alt((
+        value(DataKind::Node, terminated(tag("$NodeData"), eol)),
+        value(DataKind::Element, terminated(tag("$ElementData"), eol)),
+        value(DataKind::ElementNode, terminated(tag("$ElementNodeData"), eol)),
+    ))(input)
+}
+
+/// The section name, as used in errors.
+pub(crate) fn section_name(kind: DataKind) -> &'static str {
+    match kind {
+        DataKind::Node => "$NodeData",
+        DataKind::Element => "$ElementData",
+        DataKind::ElementNode => "$ElementNodeData",
+    }
+}
+
+fn section_end(kind: DataKind) -> &'static str {
+    match kind {
+        DataKind::Node => "$EndNodeData",
+        DataKind::Element => "$EndElementData",
+        DataKind::ElementNode => "$EndElementNodeData",
+    }
+}
+
+/// Reads one data section, returning the view name and its step.
+///
+/// Node and element tags are `int`s in 2.2 files and `size_t`s in 4.1 files,
+/// so they're read by `entity_tag`.
+pub(crate) fn parse_data<'a, E, T>(input: &'a [u8], entity_tag: T) -> IResult<&'a [u8], (String, DataKind, DataStep)>
+where
+    E: Encoding,
+    T: Fn(&'a [u8]) -> IResult<&'a [u8], Tag>,
+{
+    let (input, kind) = data_header(input)?;
+    let (input, mut string_tags) = cut(tag_lines(string_tag))(input)?;
+    let (input, real_tags) = cut(tag_lines(Ascii::double))(input)?;
+    let (rest, int_tags) = cut(tag_lines(Ascii::int))(input)?;
+    // time step, number of components and number of entries are required
+    let int_tag = |i: usize| int_tags.get(i).copied();
+    let time_step = int_tag(0).and_then(|step| u64::try_from(step).ok());
+    let num_components = int_tag(1).and_then(|components| usize::try_from(components).ok());
+    let num_entries = int_tag(2).and_then(|entries| usize::try_from(entries).ok());
+    let (time_step, num_components, num_entries) = match (time_step, num_components, num_entries) {
+        (Some(step), Some(components), Some(entries)) if components > 0 => (step, components, entries),
+        _ => return fail(input, ParseErrorKind::Nom(nom::error::ErrorKind::Verify)),
+    };
+    let partition = int_tag(3).and_then(|partition| u64::try_from(partition).ok());
+    let entry = |input| {
+        let (input, tag) = entity_tag(input)?;
+        let (input, num_nodes) = match kind {
+            DataKind::ElementNode => map_res(E::int, usize::try_from)(input)?,
+            DataKind::Node | DataKind::Element => (input, 1),
+        };
+        let (input, values) = count(E::double, num_nodes.saturating_mul(num_components))(input)?;
+        Ok((input, DataEntry { tag, values }))
+    };
+    let (input, entries) = cut(count(entry, num_entries))(rest)?;
+    let (input, _) = cut(preceded(multispace0, terminated(tag(section_end(kind)), eol)))(input)?;
+
+    let mut string_tags = string_tags.drain(..);
+    let name = string_tags.next().unwrap_or_default();
+    let step = DataStep {
+        interpolation_scheme: string_tags.next(),
+        time: real_tags.first().copied().unwrap_or(0.0),
+        time_step,
+        num_components,
+        partition,
+        entries,
+    };
+    Ok((input, (name, kind, step)))
+}
+
+/// Interpolation schemes are text in every version and storage.
+pub(crate) fn parse_interpolation_scheme(input: &[u8]) -> IResult<&[u8], InterpolationScheme> {
+    let (input, _) = terminated(tag("$InterpolationScheme"), eol)(input)?;
+    let (input, name) = cut(terminated(string_tag, eol))(input)?;
+    let (input, num_topologies) = cut(Ascii::size_t)(input)?;
+    let (input, topologies) = cut(count(parse_topology_interpolation, num_topologies as usize))(input)?;
+    let (input, _) = cut(preceded(multispace0, terminated(tag("$EndInterpolationScheme"), eol)))(input)?;
+    Ok((input, InterpolationScheme { name, topologies }))
+}
+
+fn parse_topology_interpolation(input: &[u8]) -> IResult<&[u8], TopologyInterpolation> {
+    let (input, topology) = map_res(Ascii::size_t, u32::try_from)(input)?;
+    let (input, num_matrices) = Ascii::size_t(input)?;
+    let (input, matrices) = count(parse_matrix, num_matrices as usize)(input)?;
+    Ok((input, TopologyInterpolation { topology, matrices }))
+}
+
+fn parse_matrix(input: &[u8]) -> IResult<&[u8], Matrix> {
+    let (input, rows) = map_res(Ascii::size_t, usize::try_from)(input)?;
+    let (input, columns) = map_res(Ascii::size_t, usize::try_from)(input)?;
+    let (input, values) = count(Ascii::double, rows.saturating_mul(columns))(input)?;
+    Ok((input, Matrix { rows, columns, values }))
+}
+
+/// A count followed by that many tags, one per line.
+fn tag_lines<'a, O, F>(line: F) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Vec<O>>
+where
+    F: Fn(&'a [u8]) -> IResult<&'a [u8], O>,
+{
+    move |input| {
+        let (input, num_tags) = terminated(Ascii::size_t, eol)(input)?;
+        count(terminated(&line, eol), num_tags as usize)(input)
+    }
+}
+
+/// A quoted string, though Gmsh also accepts bare ones.
+fn string_tag(input: &[u8]) -> IResult<&[u8], String> {
+    let quoted = delimited(char('"'), take_until("\""), char('"'));
+    let bare = take_till(|c| c == b'\r' || c == b'\n');
+    let (input, string) = preceded(space0, alt((quoted, bare)))(input)?;
+    Ok((input, String::from_utf8_lossy(string).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_debug_snapshot;
+
+    #[test]
+    fn element_node_data() {
+        let input = b"$ElementNodeData\n2\n\"stress\"\n\"scheme\"\n1\n0.5\n4\n2\n1\n1\n3\n7 2 1.5 2.5\n$EndElementNodeData\n";
+        let (rest, data) = parse_data::<Ascii, _>(input, Ascii::size_t).unwrap();
+        assert!(rest.is_empty());
+        assert_debug_snapshot!(data);
+    }
+
+    #[test]
+    fn interpolation_scheme() {
+        let input = b"$InterpolationScheme\n\"linear\"\n1\n3\n2\n3 3\n1 -1 -1\n0 1 0\n0 0 1\n3 2\n0 0\n1 0\n0 1\n$EndInterpolationScheme\n";
+        let (rest, scheme) = parse_interpolation_scheme(input).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(scheme.name, "linear");
+        let triangle = scheme.topology(3).unwrap();
+        assert_eq!(triangle.matrices.len(), 2);
+        assert_eq!(triangle.matrices[0].get(0, 1), Some(-1.0));
+        assert_eq!(triangle.matrices[1].get(2, 1), Some(1.0));
+        assert_eq!(triangle.matrices[1].get(3, 0), None);
+    }
+
+    #[test]
+    fn missing_int_tags() {
+        let input = b"$NodeData\n1\n\"temperature\"\n0\n2\n0\n1\n1 20\n$EndNodeData\n";
+        assert!(parse_data::<Ascii, _>(input, Ascii::size_t).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Warnings about files that could still be read.
+use super::error::{Location, ParseError, ParseErrorKind};
+use crate::Tag;
+
+use nom::AsBytes;
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+/// Options for reading `msh` files.
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// Fail on warnings, such as a section giving the wrong number of entries.
+    pub strict: bool,
+    /// Fold elements repeated once per physical group in 2.2 files into one, see
+    /// [`Msh::merge_duplicate_elements`](crate::Msh::merge_duplicate_elements). On by default.
+    pub merge_duplicate_elements: bool,
+    /// Parse large 2.2 ASCII `$Nodes` and `$Elements` sections on several
+    /// threads, giving the same results. Needs the `parallel` feature, and is
+    /// ignored without it. Off by default.
+    pub parallel: bool,
+    /// Check that elements only use nodes read before them, see
+    /// [`DiagnosticKind::DanglingNode`]. This keeps every node tag read, so it's
+    /// off by default.
+    pub check_nodes: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> ReadOptions {
+        ReadOptions { strict: false, merge_duplicate_elements: true, parallel: false, check_nodes: false }
+    }
+}
+
+/// Something unusual about a file that didn't stop it from being read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub location: Location,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Expected in valid files, e.g. sections this crate doesn't read.
+    Info,
+    /// Likely a problem with the file. Errors in strict mode.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// A section's header doesn't match its number of entries.
+    CountMismatch { expected: u64, found: u64 },
+    /// Element tags that don't fit the 2.2 layout were skipped, or negative ones read as zero.
+    IgnoredElementTags,
+    /// An element refers to a node that wasn't read before it, see [`ReadOptions::check_nodes`].
+    DanglingNode { element: Tag, node: Tag },
+    /// A section was skipped.
+    UnknownSection { name: String },
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use DiagnosticKind::*;
+        match self {
+            CountMismatch { expected, found } => write!(fmt, "expected {} entries but found {}", expected, found),
+            IgnoredElementTags => write!(fmt, "skipped unrecognized element tags"),
+            DanglingNode { element, node } => write!(fmt, "element {} refers to undefined node {}", element, node),
+            UnknownSection { name } => write!(fmt, "skipped unknown section ${}", name),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+        };
+        write!(fmt, "{}: {} at {}", severity, self.kind, self.location)
+    }
+}
+
+/// Collects diagnostics while parsing `input`.
+///
+/// Parsers only share a reference, so diagnostics are collected through a `RefCell`.
+pub(crate) struct Diagnostics<'a> {
+    input: &'a [u8],
+    strict: bool,
+    #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+    parallel: bool,
+    check_nodes: bool,
+    /// Found in earlier pieces of the same file, which [`warn_once`](Diagnostics::warn_once) also checks.
+    earlier: &'a [Diagnostic],
+    found: RefCell<Vec<Diagnostic>>,
+    /// The offset and line of the last diagnostic, to count lines on from.
+    last: Cell<(usize, usize)>,
+}
+
+impl<'a> Diagnostics<'a> {
+    pub(crate) fn new(input: &'a [u8], options: &ReadOptions) -> Diagnostics<'a> {
+        Diagnostics::following(input, options, &[])
+    }
+
+    /// Collects diagnostics for a piece of a file, following pieces that found `earlier`.
+    pub(crate) fn following(input: &'a [u8], options: &ReadOptions, earlier: &'a [Diagnostic]) -> Diagnostics<'a> {
+        let (strict, parallel, check_nodes) = (options.strict, options.parallel, options.check_nodes);
+        let found = RefCell::new(Vec::new());
+        Diagnostics { input, strict, parallel, check_nodes, earlier, found, last: Cell::new((0, 1)) }
+    }
+
+    /// Whether large sections may be parsed on several threads, see [`ReadOptions::parallel`].
+    #[cfg(feature = "parallel")]
+    pub(crate) fn parallel(&self) -> bool {
+        self.parallel
+    }
+
+    /// Whether elements are checked for dangling nodes, see [`ReadOptions::check_nodes`].
+    pub(crate) fn check_nodes(&self) -> bool {
+        self.check_nodes
+    }
+
+    /// An empty collector for the same input, for parsing part of it on another thread.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn fork(&self) -> Diagnostics<'a> {
+        let found = RefCell::new(Vec::new());
+        let (strict, check_nodes, earlier) = (self.strict, self.check_nodes, self.earlier);
+        let last = self.last.clone();
+        Diagnostics { input: self.input, strict, parallel: false, check_nodes, earlier, found, last }
+    }
+
+    /// Adds what a [`fork`](Diagnostics::fork) found, like [`warn_once`](Diagnostics::warn_once) would.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn merge_once(&self, fork: Diagnostics) {
+        let mut found = self.found.borrow_mut();
+        for diagnostic in fork.into_vec() {
+            if !found.iter().any(|seen| seen.kind == diagnostic.kind) {
+                found.push(diagnostic);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn lenient(input: &'a [u8]) -> Diagnostics<'a> {
+        Diagnostics::new(input, &ReadOptions::default())
+    }
+
+    /// Records a warning at `at`, or fails in strict mode.
+    pub(crate) fn warn<I: AsBytes>(
+        &self,
+        at: I,
+        section: &'static str,
+        kind: DiagnosticKind,
+    ) -> Result<(), nom::Err<ParseError<I>>> {
+        if self.strict {
+            let kind = match kind {
+                DiagnosticKind::CountMismatch { expected, found } => ParseErrorKind::Count { expected, found },
+                DiagnosticKind::DanglingNode { element, node } => ParseErrorKind::DanglingNode { element, node },
+                kind => ParseErrorKind::Warning(kind),
+            };
+            return Err(nom::Err::Failure(ParseError::new(at, kind).in_section(section)));
+        }
+        self.push(Severity::Warning, at.as_bytes(), Some(section), kind);
+        Ok(())
+    }
+
+    /// Like [`warn`](Diagnostics::warn), but only records the first occurrence of `kind`.
+    pub(crate) fn warn_once<I: AsBytes>(
+        &self,
+        at: I,
+        section: &'static str,
+        kind: DiagnosticKind,
+    ) -> Result<(), nom::Err<ParseError<I>>> {
+        if self.earlier.iter().chain(self.found.borrow().iter()).any(|diagnostic| diagnostic.kind == kind) {
+            return Ok(());
+        }
+        self.warn(at, section, kind)
+    }
+
+    pub(crate) fn info<I: AsBytes>(&self, at: I, section: Option<&'static str>, kind: DiagnosticKind) {
+        self.push(Severity::Info, at.as_bytes(), section, kind);
+    }
+
+    fn push(&self, severity: Severity, at: &[u8], section: Option<&'static str>, kind: DiagnosticKind) {
+        // diagnostics mostly come in order, so lines aren't counted from the start each time
+        let location = Location::counting_from(self.input, at, section, self.last.get());
+        self.last.set((location.offset, location.line));
+        self.found.borrow_mut().push(Diagnostic { severity, kind, location });
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<Diagnostic> {
+        self.found.into_inner()
+    }
+}
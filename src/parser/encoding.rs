@@ -0,0 +1,76 @@
+//! Number encodings shared by the binary and 4.1 readers.
+use super::*;
+
+use nom::character::complete::multispace0;
+use nom::combinator::{opt, recognize};
+use nom::number::complete::{be_f64, be_i32, be_u64, le_f64, le_i32, le_u64};
+use nom::sequence::{pair, preceded};
+
+/// How `int`, `size_t` and `double` values are stored.
+pub(crate) trait Encoding {
+    fn int(input: &[u8]) -> IResult<&[u8], i32>;
+    fn size_t(input: &[u8]) -> IResult<&[u8], u64>;
+    fn double(input: &[u8]) -> IResult<&[u8], f64>;
+}
+
+/// Whitespace-separated decimal numbers.
+pub(crate) struct Ascii;
+
+impl Encoding for Ascii {
+    fn int(input: &[u8]) -> IResult<&[u8], i32> {
+        let signed = recognize(pair(opt(one_of("+-")), digit1));
+        preceded(multispace0, map_res(map_res(signed, std::str::from_utf8), i32::from_str))(input)
+    }
+
+    fn size_t(input: &[u8]) -> IResult<&[u8], u64> {
+        preceded(multispace0, map_res(map_res(digit1, std::str::from_utf8), u64::from_str))(input)
+    }
+
+    fn double(input: &[u8]) -> IResult<&[u8], f64> {
+        preceded(multispace0, double)(input)
+    }
+}
+
+/// Running out of input in a binary block means the block is cut short.
+fn truncated(err: Err<ParseError<&[u8]>>) -> Err<ParseError<&[u8]>> {
+    match err {
+        Err::Error(ParseError { input, kind: ParseErrorKind::Nom(nom::error::ErrorKind::Eof), section }) => {
+            Err::Failure(ParseError { input, kind: ParseErrorKind::Truncated, section })
+        }
+        err => err,
+    }
+}
+
+/// Little-endian `int`s, 8-byte `size_t`s and `double`s.
+pub(crate) struct BinaryLe;
+
+impl Encoding for BinaryLe {
+    fn int(input: &[u8]) -> IResult<&[u8], i32> {
+        le_i32(input).map_err(truncated)
+    }
+
+    fn size_t(input: &[u8]) -> IResult<&[u8], u64> {
+        le_u64(input).map_err(truncated)
+    }
+
+    fn double(input: &[u8]) -> IResult<&[u8], f64> {
+        le_f64(input).map_err(truncated)
+    }
+}
+
+/// Big-endian `int`s, 8-byte `size_t`s and `double`s.
+pub(crate) struct BinaryBe;
+
+impl Encoding for BinaryBe {
+    fn int(input: &[u8]) -> IResult<&[u8], i32> {
+        be_i32(input).map_err(truncated)
+    }
+
+    fn size_t(input: &[u8]) -> IResult<&[u8], u64> {
+        be_u64(input).map_err(truncated)
+    }
+
+    fn double(input: &[u8]) -> IResult<&[u8], f64> {
+        be_f64(input).map_err(truncated)
+    }
+}
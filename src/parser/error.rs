@@ -0,0 +1,229 @@
+//! Parse errors and where they happened.
+use super::diagnostics::DiagnosticKind;
+use super::MshVersion;
+use crate::Tag;
+
+use thiserror::Error;
+
+use nom::error::ErrorKind;
+
+use std::fmt;
+
+#[derive(Error, Debug)]
+pub enum MshError {
+    #[error("IO error ({source})")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("bad header at {location}")]
+    Header { location: Location },
+    #[error("{version:?} files can't be memory-mapped, only binary ones")]
+    NotBinary { version: MshVersion },
+    #[error("unknown endianness marker {marker:?} in binary header")]
+    Endianness {
+        marker: [u8; 4],
+    },
+    #[error("expected {expected} entries but found {found} at {location}")]
+    Count { expected: u64, found: u64, location: Location },
+    #[error("unsupported element type {ty} at {location}")]
+    ElementType { ty: i64, location: Location },
+    #[error("element {element} refers to undefined node {node} at {location}")]
+    DanglingNode { element: Tag, node: Tag, location: Location },
+    #[error("binary block ends early at {location}")]
+    Truncated { location: Location },
+    #[error("unexpected input ({kind:?}) at {location}")]
+    Syntax { kind: ErrorKind, location: Location },
+    /// A warning, in strict mode.
+    #[error("{kind} at {location}")]
+    Warning { kind: DiagnosticKind, location: Location },
+}
+
+pub type MshResult<T> = std::result::Result<T, MshError>;
+
+impl MshError {
+    /// Where in the file the error happened, for errors that come from its contents.
+    pub fn location(&self) -> Option<&Location> {
+        use MshError::*;
+        match self {
+            Io { .. } | NotBinary { .. } | Endianness { .. } => None,
+            Header { location }
+            | Count { location, .. }
+            | ElementType { location, .. }
+            | DanglingNode { location, .. }
+            | Truncated { location }
+            | Syntax { location, .. }
+            | Warning { location, .. } => Some(location),
+        }
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        use MshError::*;
+        match self {
+            Io { .. } | NotBinary { .. } | Endianness { .. } => None,
+            Header { location }
+            | Count { location, .. }
+            | ElementType { location, .. }
+            | DanglingNode { location, .. }
+            | Truncated { location }
+            | Syntax { location, .. }
+            | Warning { location, .. } => Some(location),
+        }
+    }
+
+    /// Moves the error's location from a piece of the input to the whole input,
+    /// see [`Location::shift`].
+    pub(crate) fn shift(mut self, offset: usize, lines: usize) -> MshError {
+        if let Some(location) = self.location_mut() {
+            location.shift(offset, lines);
+        }
+        self
+    }
+}
+
+/// A position in the input.
+///
+/// Lines are counted by newlines, so inside binary blocks only the byte offset
+/// is meaningful.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The section being read, e.g. `$Nodes`.
+    pub section: Option<&'static str>,
+    /// 1-based line number.
+    pub line: usize,
+    /// Byte offset from the start of the input.
+    pub offset: usize,
+}
+
+impl Location {
+    /// Locates `rest`, a slice of `input`.
+    pub(crate) fn new(input: &[u8], rest: &[u8], section: Option<&'static str>) -> Location {
+        Location::counting_from(input, rest, section, (0, 1))
+    }
+
+    /// Like [`new`](Location::new), counting lines on from an `(offset, line)`
+    /// already known, if it comes before `rest`.
+    pub(crate) fn counting_from(
+        input: &[u8],
+        rest: &[u8],
+        section: Option<&'static str>,
+        known: (usize, usize),
+    ) -> Location {
+        let offset = (rest.as_ptr() as usize).saturating_sub(input.as_ptr() as usize).min(input.len());
+        let (start, start_line) = if known.0 <= offset { known } else { (0, 1) };
+        let line = input[start..offset].iter().filter(|&&byte| byte == b'\n').count() + start_line;
+        Location { section, line, offset }
+    }
+
+    /// Moves a location in a piece of the input that starts `offset` bytes and
+    /// `lines` newlines into the whole input.
+    pub(crate) fn shift(&mut self, offset: usize, lines: usize) {
+        self.offset += offset;
+        self.line += lines;
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "line {} (byte {})", self.line, self.offset)?;
+        match self.section {
+            Some(section) => write!(fmt, " in {}", section),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The error type of all parsers.
+///
+/// Only the remaining input is kept, which is turned into a [`Location`] once
+/// parsing stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError<I> {
+    pub input: I,
+    pub kind: ParseErrorKind,
+    pub section: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    Nom(ErrorKind),
+    Header,
+    Endianness([u8; 4]),
+    Count { expected: u64, found: u64 },
+    ElementType(i64),
+    DanglingNode { element: Tag, node: Tag },
+    Truncated,
+    Warning(DiagnosticKind),
+}
+
+impl<I> ParseError<I> {
+    pub fn new(input: I, kind: ParseErrorKind) -> ParseError<I> {
+        ParseError { input, kind, section: None }
+    }
+
+    /// Records the innermost section an error happened in.
+    pub(crate) fn in_section(mut self, section: &'static str) -> ParseError<I> {
+        self.section = self.section.or(Some(section));
+        self
+    }
+
+    pub(crate) fn map_input<J>(self, f: impl FnOnce(I) -> J) -> ParseError<J> {
+        ParseError { input: f(self.input), kind: self.kind, section: self.section }
+    }
+}
+
+impl<I> nom::error::ParseError<I> for ParseError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        ParseError::new(input, ParseErrorKind::Nom(kind))
+    }
+
+    /// Keeps the innermost error, which is the most specific.
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl ParseError<&[u8]> {
+    /// Converts an error from parsing `input` into an [`MshError`].
+    pub(crate) fn into_msh_error(self, input: &[u8]) -> MshError {
+        let location = Location::new(input, self.input, self.section);
+        match self.kind {
+            ParseErrorKind::Nom(kind) => MshError::Syntax { kind, location },
+            ParseErrorKind::Header => MshError::Header { location },
+            ParseErrorKind::Endianness(marker) => MshError::Endianness { marker },
+            ParseErrorKind::Count { expected, found } => MshError::Count { expected, found, location },
+            ParseErrorKind::ElementType(ty) => MshError::ElementType { ty, location },
+            ParseErrorKind::DanglingNode { element, node } => MshError::DanglingNode { element, node, location },
+            ParseErrorKind::Truncated => MshError::Truncated { location },
+            ParseErrorKind::Warning(kind) => MshError::Warning { kind, location },
+        }
+    }
+}
+
+pub(crate) fn into_msh_error(input: &[u8], err: nom::Err<ParseError<&[u8]>>) -> MshError {
+    match err {
+        nom::Err::Error(err) | nom::Err::Failure(err) => err.into_msh_error(input),
+        // only complete parsers are used, but treat it like running out of input
+        nom::Err::Incomplete(_) => {
+            let location = Location::new(input, &input[input.len()..], None);
+            MshError::Truncated { location }
+        }
+    }
+}
+
+/// Tags errors from a section's parser with the section name.
+pub(crate) fn in_section<I, O>(
+    section: &'static str,
+    res: nom::IResult<I, O, ParseError<I>>,
+) -> nom::IResult<I, O, ParseError<I>> {
+    res.map_err(|err| match err {
+        nom::Err::Error(err) => nom::Err::Error(err.in_section(section)),
+        nom::Err::Failure(err) => nom::Err::Failure(err.in_section(section)),
+        nom::Err::Incomplete(needed) => nom::Err::Incomplete(needed),
+    })
+}
+
+/// Stops parsing with a specific error.
+pub(crate) fn fail<I, O>(input: I, kind: ParseErrorKind) -> nom::IResult<I, O, ParseError<I>> {
+    Err(nom::Err::Failure(ParseError::new(input, kind)))
+}
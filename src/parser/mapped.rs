@@ -0,0 +1,624 @@
+//! Reading binary files through a memory map.
+use super::*;
+use super::encoding::Encoding;
+
+use memmap2::Mmap;
+
+use nom::sequence::tuple;
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fs::File;
+
+/// A binary `msh` file whose nodes and elements stay in the file until asked for.
+///
+/// Opening the file only locates the `$Nodes` and `$Elements` blocks, skipping
+/// over their records, so it takes about as long for a huge mesh as for a small
+/// one. Nodes and elements are decoded one at a time by [`node`](MappedMsh::node),
+/// [`nodes`](MappedMsh::nodes) and [`elements`](MappedMsh::elements), or all at
+/// once by [`to_msh`](MappedMsh::to_msh). The other sections are small and read
+/// as usual, see [`rest`](MappedMsh::rest).
+///
+/// The coordinates and tags of 4.1 node blocks can be borrowed straight from the
+/// file when its byte order is the machine's, see
+/// [`node_block_coordinates`](MappedMsh::node_block_coordinates).
+///
+/// Records aren't checked: elements aren't matched with their nodes, and 2.2
+/// tags are taken as unsigned. Only the first of concatenated meshes is read.
+///
+/// ```no_run
+/// # fn main() -> msh_rw::parser::MshResult<()> {
+/// use msh_rw::parser::MappedMsh;
+///
+/// let mesh = MappedMsh::open("mesh.msh")?;
+/// let lowest = mesh.nodes().map(|node| node.z).fold(f64::INFINITY, f64::min);
+/// println!("{} nodes, the lowest at z = {}", mesh.num_nodes(), lowest);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MappedMsh {
+    map: Mmap,
+    version: MshVersion,
+    big_endian: bool,
+    node_blocks: Vec<NodeBlock>,
+    elt_blocks: Vec<EltBlock>,
+    num_nodes: usize,
+    num_elts: usize,
+    rest: Msh,
+    merge_duplicate_elements: bool,
+}
+
+/// Nodes stored together, by `$Nodes` section in 2.2 files and by entity in 4.1 files.
+#[derive(Debug)]
+struct NodeBlock {
+    /// Index of the first node among all nodes.
+    first: usize,
+    len: usize,
+    layout: NodeLayout,
+}
+
+#[derive(Debug)]
+enum NodeLayout {
+    /// 2.2 records: an `int` tag and three `double`s.
+    Records { start: usize },
+    /// All `size_t` tags of a 4.1 block, followed by all coordinates.
+    Split { entity: EntityId, tags: usize, coords: usize, parametric: bool },
+}
+
+/// Elements sharing a type, and in 4.1 files an entity.
+#[derive(Debug)]
+struct EltBlock {
+    first: usize,
+    len: usize,
+    ty: MeshShape,
+    num_nodes: usize,
+    start: usize,
+    layout: EltLayout,
+}
+
+#[derive(Debug)]
+enum EltLayout {
+    /// `int` records: the tag, `num_tags` tags, then the nodes.
+    Msh2 { num_tags: usize },
+    /// `size_t` records: the tag, then the nodes.
+    Msh4 { entity: Tag, physical_groups: Vec<Tag> },
+}
+
+/// 2.2 node records: an `int` and three `double`s.
+const NODE_RECORD_MSH2: usize = 4 + 3 * 8;
+
+impl MappedMsh {
+    pub fn open<P: AsRef<Path>>(path: P) -> MshResult<MappedMsh> {
+        Ok(MappedMsh::open_with(path, &ReadOptions::default())?.0)
+    }
+
+    /// Like [`open`](MappedMsh::open), also returning what was noticed in the sections read.
+    ///
+    /// ASCII files can't be mapped and give [`MshError::NotBinary`].
+    pub fn open_with<P: AsRef<Path>>(path: P, options: &ReadOptions) -> MshResult<(MappedMsh, Vec<Diagnostic>)> {
+        let file = File::open(path)?;
+        // Safety: like any memory map, this relies on the file not being
+        // changed by others while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+        let version = parse_header(&map)?;
+        let big_endian = match version {
+            MshVersion::BinaryLeV22 | MshVersion::BinaryLeV41 => false,
+            MshVersion::BinaryBeV22 | MshVersion::BinaryBeV41 => true,
+            MshVersion::AsciiV22 | MshVersion::AsciiV41 => return Err(MshError::NotBinary { version }),
+        };
+        let diagnostics = Diagnostics::new(&map, options);
+        let mut rest = Msh::new();
+        let mut index = Index::default();
+        let result = {
+            let mut visit = Visit::new(&mut rest);
+            visit.header(version);
+            match version {
+                MshVersion::BinaryLeV22 => index_sections::<BinaryLe>(&map, false, &mut index, &mut visit, &diagnostics),
+                MshVersion::BinaryLeV41 => index_sections::<BinaryLe>(&map, true, &mut index, &mut visit, &diagnostics),
+                MshVersion::BinaryBeV22 => index_sections::<BinaryBe>(&map, false, &mut index, &mut visit, &diagnostics),
+                _ => index_sections::<BinaryBe>(&map, true, &mut index, &mut visit, &diagnostics),
+            }
+        };
+        if let Err(err) = result {
+            return Err(into_msh_error(&map, err));
+        }
+        let diagnostics = diagnostics.into_vec();
+        let mesh = MappedMsh {
+            version,
+            big_endian,
+            node_blocks: index.node_blocks,
+            elt_blocks: index.elt_blocks,
+            num_nodes: index.num_nodes,
+            num_elts: index.num_elts,
+            rest,
+            merge_duplicate_elements: options.merge_duplicate_elements && version.is_v22(),
+            map,
+        };
+        Ok((mesh, diagnostics))
+    }
+
+    pub fn version(&self) -> MshVersion {
+        self.version
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.num_nodes
+    }
+
+    /// Counts 2.2 elements repeated once per physical group separately.
+    pub fn num_elements(&self) -> usize {
+        self.num_elts
+    }
+
+    /// The `i`th node of the file, starting from 0.
+    pub fn node(&self, i: usize) -> Option<Node> {
+        let block = &self.node_blocks[find_block(&self.node_blocks, i, |block| (block.first, block.len))?];
+        Some(self.block_node(block, i - block.first))
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = Node> + '_ {
+        self.node_blocks.iter().flat_map(move |block| (0..block.len).map(move |i| self.block_node(block, i)))
+    }
+
+    /// The `i`th element of the file, starting from 0.
+    pub fn element(&self, i: usize) -> Option<MeshElt> {
+        let block = &self.elt_blocks[find_block(&self.elt_blocks, i, |block| (block.first, block.len))?];
+        Some(self.block_element(block, i - block.first))
+    }
+
+    /// Elements as they are stored, see [`num_elements`](MappedMsh::num_elements).
+    pub fn elements(&self) -> impl Iterator<Item = MeshElt> + '_ {
+        self.elt_blocks.iter().flat_map(move |block| (0..block.len).map(move |i| self.block_element(block, i)))
+    }
+
+    /// One per `$Nodes` section in 2.2 files, one per entity in 4.1 files.
+    pub fn num_node_blocks(&self) -> usize {
+        self.node_blocks.len()
+    }
+
+    /// The `x, y, z` coordinates of a 4.1 node block, borrowed from the file.
+    ///
+    /// `None` unless the file has the machine's byte order and the block has no
+    /// parametric coordinates and happens to be aligned. 2.2 files store tags
+    /// between coordinates, so their blocks are never borrowed.
+    pub fn node_block_coordinates(&self, block: usize) -> Option<&[f64]> {
+        let block = self.node_blocks.get(block)?;
+        match block.layout {
+            NodeLayout::Split { coords, parametric: false, .. } => self.view(coords, 3 * block.len),
+            _ => None,
+        }
+    }
+
+    /// The node tags of a 4.1 node block, borrowed from the file like
+    /// [`node_block_coordinates`](MappedMsh::node_block_coordinates).
+    pub fn node_block_tags(&self, block: usize) -> Option<&[u64]> {
+        let block = self.node_blocks.get(block)?;
+        match block.layout {
+            NodeLayout::Split { tags, .. } => self.view(tags, block.len),
+            _ => None,
+        }
+    }
+
+    /// Everything but the nodes and elements.
+    pub fn rest(&self) -> &Msh {
+        &self.rest
+    }
+
+    /// Reads every node and element into a [`Msh`], like [`Msh::from_file`] would.
+    pub fn to_msh(&self) -> Msh {
+        let mut msh = self.rest.clone();
+        msh.nodes = self.nodes().collect();
+        msh.elts = self.elements().collect();
+        if self.merge_duplicate_elements {
+            msh.merge_duplicate_elements();
+        }
+        msh
+    }
+
+    fn block_node(&self, block: &NodeBlock, i: usize) -> Node {
+        match block.layout {
+            NodeLayout::Records { start } => {
+                let record = start + i * NODE_RECORD_MSH2;
+                Node {
+                    tag: self.u32_at(record).into(),
+                    x: self.f64_at(record + 4),
+                    y: self.f64_at(record + 12),
+                    z: self.f64_at(record + 20),
+                    entity: None,
+                    parametric: None,
+                }
+            }
+            NodeLayout::Split { entity, tags, coords, parametric } => {
+                let num_params = if parametric { entity.dim.as_u8() as usize } else { 0 };
+                let point = coords + i * (3 + num_params) * 8;
+                let parametric = if parametric {
+                    let mut params = [0.0; 3];
+                    for (j, param) in params.iter_mut().enumerate().take(num_params) {
+                        *param = self.f64_at(point + (3 + j) * 8);
+                    }
+                    Some(params)
+                } else {
+                    None
+                };
+                Node {
+                    tag: self.u64_at(tags + i * 8),
+                    x: self.f64_at(point),
+                    y: self.f64_at(point + 8),
+                    z: self.f64_at(point + 16),
+                    entity: Some(entity),
+                    parametric,
+                }
+            }
+        }
+    }
+
+    fn block_element(&self, block: &EltBlock, i: usize) -> MeshElt {
+        match block.layout {
+            EltLayout::Msh2 { num_tags } => {
+                let record = block.start + i * (1 + num_tags + block.num_nodes) * 4;
+                let int = |j: usize| record + j * 4;
+                let tags = (0..num_tags).map(|j| i64::from(self.i32_at(int(1 + j)))).collect::<Vec<_>>();
+                let nodes = (0..block.num_nodes).map(|j| self.u32_at(int(1 + num_tags + j)).into()).collect();
+                let mut elt = MeshElt::new(0, block.ty, nodes);
+                EltInfo::from_tags(&tags).fill(self.u32_at(record).into(), block.ty, &mut elt);
+                elt
+            }
+            EltLayout::Msh4 { entity, ref physical_groups } => {
+                let record = block.start + i * (1 + block.num_nodes) * 8;
+                let nodes = (0..block.num_nodes).map(|j| self.u64_at(record + (1 + j) * 8)).collect();
+                MeshElt {
+                    physical_groups: physical_groups.clone(),
+                    geometry: Some(entity),
+                    ..MeshElt::new(self.u64_at(record), block.ty, nodes)
+                }
+            }
+        }
+    }
+
+    /// Borrows `len` values starting at byte `start`.
+    ///
+    /// Only for types that any bytes are a valid value of, i.e. `u64` and `f64`.
+    fn view<T>(&self, start: usize, len: usize) -> Option<&[T]> {
+        if self.big_endian != cfg!(target_endian = "big") {
+            return None;
+        }
+        let bytes = &self.map[start..start + len * std::mem::size_of::<T>()];
+        // Safety: the caller only asks for plain numbers
+        let (head, values, _) = unsafe { bytes.align_to::<T>() };
+        if head.is_empty() && values.len() == len {
+            Some(values)
+        } else {
+            None
+        }
+    }
+
+    fn bytes(&self, at: usize, out: &mut [u8]) {
+        out.copy_from_slice(&self.map[at..at + out.len()]);
+    }
+
+    fn u32_at(&self, at: usize) -> u32 {
+        let mut bytes = [0; 4];
+        self.bytes(at, &mut bytes);
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+
+    fn i32_at(&self, at: usize) -> i32 {
+        self.u32_at(at) as i32
+    }
+
+    fn u64_at(&self, at: usize) -> u64 {
+        let mut bytes = [0; 8];
+        self.bytes(at, &mut bytes);
+        if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) }
+    }
+
+    fn f64_at(&self, at: usize) -> f64 {
+        f64::from_bits(self.u64_at(at))
+    }
+}
+
+/// The block holding item `i`, given each block's first item and length.
+fn find_block<B>(blocks: &[B], i: usize, range: impl Fn(&B) -> (usize, usize)) -> Option<usize> {
+    let found = blocks.binary_search_by(|block| {
+        let (first, len) = range(block);
+        if first + len <= i {
+            Ordering::Less
+        } else if first > i {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+    found.ok()
+}
+
+#[derive(Default)]
+struct Index {
+    node_blocks: Vec<NodeBlock>,
+    elt_blocks: Vec<EltBlock>,
+    num_nodes: usize,
+    num_elts: usize,
+}
+
+impl Index {
+    fn push_nodes(&mut self, len: usize, layout: NodeLayout) {
+        self.node_blocks.push(NodeBlock { first: self.num_nodes, len, layout });
+        self.num_nodes += len;
+    }
+
+    fn push_elements(&mut self, len: usize, ty: MeshShape, num_nodes: usize, start: usize, layout: EltLayout) {
+        self.elt_blocks.push(EltBlock { first: self.num_elts, len, ty, num_nodes, start, layout });
+        self.num_elts += len;
+    }
+}
+
+/// Locates the nodes and elements of the mesh at the start of `file`, handing
+/// the other sections to `visit`.
+fn index_sections<'a, E: Encoding>(
+    file: &'a [u8],
+    msh4: bool,
+    index: &mut Index,
+    visit: &mut Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (mut input, _) = msh_header(file)?;
+    // like whole-file parsing, stop at the next mesh or anything that isn't a section
+    while let Ok((_, name)) = unknown_section_header(input) {
+        let (rest, _) = match (name, msh4) {
+            ("Nodes", false) => in_section("$Nodes", index_nodes_msh2(file, input, index))?,
+            ("Nodes", true) => in_section("$Nodes", index_nodes_msh4::<E>(file, input, index, diagnostics))?,
+            ("Elements", false) => in_section("$Elements", index_elements_msh2::<E>(file, input, index, diagnostics))?,
+            ("Elements", true) => in_section("$Elements", index_elements_msh4::<E>(file, input, index, visit, diagnostics))?,
+            (_, false) => msh2_binary::parse_section::<E>(visit, input, diagnostics)?,
+            (_, true) => msh4::parse_section::<E>(visit, input, diagnostics)?,
+        };
+        input = rest;
+    }
+    Ok((input, ()))
+}
+
+/// Skips `len` bytes, returning where they start in `file`.
+fn skip<'a>(file: &[u8], input: &'a [u8], len: Option<usize>) -> IResult<&'a [u8], usize> {
+    match len {
+        Some(len) if len <= input.len() => Ok((&input[len..], file.len() - input.len())),
+        _ => fail(input, ParseErrorKind::Truncated),
+    }
+}
+
+fn index_nodes_msh2<'a>(file: &'a [u8], input: &'a [u8], index: &mut Index) -> IResult<&'a [u8], ()> {
+    let (input, _) = terminated(tag("$Nodes"), eol)(input)?;
+    let (input, num_nodes) = cut(msh2_binary::ascii_count)(input)?;
+    let (input, start) = skip(file, input, num_nodes.checked_mul(NODE_RECORD_MSH2))?;
+    index.push_nodes(num_nodes, NodeLayout::Records { start });
+    let (input, _) = cut(msh2_binary::section_footer("$EndNodes"))(input)?;
+    Ok((input, ()))
+}
+
+fn index_elements_msh2<'a, E: Encoding>(
+    file: &'a [u8],
+    input: &'a [u8],
+    index: &mut Index,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (count_line, _) = terminated(tag("$Elements"), eol)(input)?;
+    let (mut input, num_elts) = cut(msh2_binary::ascii_count)(count_line)?;
+    let mut found = 0;
+    while found < num_elts {
+        let (rest, block_len) = cut_result(index_element_block_msh2::<E>(file, input, index))?;
+        found += block_len;
+        input = rest;
+    }
+    if found > num_elts {
+        let kind = DiagnosticKind::CountMismatch { expected: num_elts as u64, found: found as u64 };
+        diagnostics.warn(count_line, "$Elements", kind)?;
+    }
+    let (input, _) = cut(msh2_binary::section_footer("$EndElements"))(input)?;
+    Ok((input, ()))
+}
+
+/// Returns the number of elements in the block.
+fn index_element_block_msh2<'a, E: Encoding>(file: &'a [u8], input: &'a [u8], index: &mut Index) -> IResult<&'a [u8], usize> {
+    let (rest, label) = E::int(input)?;
+    let (ty, num_nodes) = match u32::try_from(label).ok().and_then(MeshShape::from_gmsh_type).and_then(sized) {
+        Some(ty) => ty,
+        None => return fail(input, ParseErrorKind::ElementType(label.into())),
+    };
+    let (input, num_elts) = map_res(E::int, usize::try_from)(rest)?;
+    let (input, num_tags) = map_res(E::int, usize::try_from)(input)?;
+    let record = (1 + num_tags + num_nodes) * 4;
+    let (input, start) = skip(file, input, num_elts.checked_mul(record))?;
+    index.push_elements(num_elts, ty, num_nodes, start, EltLayout::Msh2 { num_tags });
+    Ok((input, num_elts))
+}
+
+fn index_nodes_msh4<'a, E: Encoding>(
+    file: &'a [u8],
+    input: &'a [u8],
+    index: &mut Index,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (count_line, _) = terminated(tag("$Nodes"), eol)(input)?;
+    let (mut input, (num_blocks, num_nodes, _, _)) =
+        cut(tuple((E::size_t, E::size_t, E::size_t, E::size_t)))(count_line)?;
+    let mut found = 0;
+    for _ in 0..num_blocks {
+        let (rest, block_len) = cut_result(index_node_block_msh4::<E>(file, input, index))?;
+        found += block_len;
+        input = rest;
+    }
+    if found != num_nodes {
+        let kind = DiagnosticKind::CountMismatch { expected: num_nodes, found };
+        diagnostics.warn(count_line, "$Nodes", kind)?;
+    }
+    let (input, _) = cut(msh4::section_footer("$EndNodes"))(input)?;
+    Ok((input, ()))
+}
+
+/// Returns the number of nodes in the block.
+fn index_node_block_msh4<'a, E: Encoding>(file: &'a [u8], input: &'a [u8], index: &mut Index) -> IResult<&'a [u8], u64> {
+    let (input, dim) = msh4::dimension::<E>(input)?;
+    let (input, entity_tag) = msh4::int_tag::<E>(input)?;
+    let (input, parametric) = E::int(input)?;
+    let (input, num_nodes) = E::size_t(input)?;
+    let len = usize::try_from(num_nodes).ok();
+    let num_params = if parametric != 0 { dim.as_u8() as usize } else { 0 };
+    let (input, tags) = skip(file, input, len.and_then(|len| len.checked_mul(8)))?;
+    let (input, coords) = skip(file, input, len.and_then(|len| len.checked_mul((3 + num_params) * 8)))?;
+    let entity = EntityId { dim, tag: entity_tag };
+    let layout = NodeLayout::Split { entity, tags, coords, parametric: parametric != 0 };
+    index.push_nodes(len.unwrap_or_default(), layout);
+    Ok((input, num_nodes))
+}
+
+fn index_elements_msh4<'a, E: Encoding>(
+    file: &'a [u8],
+    input: &'a [u8],
+    index: &mut Index,
+    visit: &Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (count_line, _) = terminated(tag("$Elements"), eol)(input)?;
+    let (mut input, (num_blocks, num_elts, _, _)) =
+        cut(tuple((E::size_t, E::size_t, E::size_t, E::size_t)))(count_line)?;
+    let mut found = 0;
+    for _ in 0..num_blocks {
+        let (rest, block_len) = cut_result(index_element_block_msh4::<E>(file, input, index, visit))?;
+        found += block_len;
+        input = rest;
+    }
+    if found != num_elts {
+        let kind = DiagnosticKind::CountMismatch { expected: num_elts, found };
+        diagnostics.warn(count_line, "$Elements", kind)?;
+    }
+    let (input, _) = cut(msh4::section_footer("$EndElements"))(input)?;
+    Ok((input, ()))
+}
+
+/// Returns the number of elements in the block.
+fn index_element_block_msh4<'a, E: Encoding>(
+    file: &'a [u8],
+    input: &'a [u8],
+    index: &mut Index,
+    visit: &Visit,
+) -> IResult<&'a [u8], u64> {
+    let (input, dim) = msh4::dimension::<E>(input)?;
+    let (input, entity_tag) = msh4::int_tag::<E>(input)?;
+    let (rest, label) = E::int(input)?;
+    let (ty, num_nodes) = match u32::try_from(label).ok().and_then(MeshShape::from_gmsh_type).and_then(sized) {
+        Some(ty) => ty,
+        None => return fail(input, ParseErrorKind::ElementType(label.into())),
+    };
+    let (input, num_elts) = E::size_t(rest)?;
+    let len = usize::try_from(num_elts).ok();
+    let (input, start) = skip(file, input, len.and_then(|len| len.checked_mul((1 + num_nodes) * 8)))?;
+    // elements take the physical groups of their entity, which come before them
+    let physical_groups = visit.entity_groups(EntityId { dim, tag: entity_tag }).to_vec();
+    let layout = EltLayout::Msh4 { entity: entity_tag, physical_groups };
+    index.push_elements(len.unwrap_or_default(), ty, num_nodes, start, layout);
+    Ok((input, num_elts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    /// Writes `msh` to a file of its own, removed when dropped.
+    struct TempMsh(PathBuf);
+
+    impl TempMsh {
+        fn new(name: &str, write: impl FnOnce(&mut File) -> std::io::Result<()>) -> TempMsh {
+            let path = std::env::temp_dir().join(format!("msh-rw-mapped-{}-{}.msh", std::process::id(), name));
+            write(&mut File::create(&path).unwrap()).unwrap();
+            TempMsh(path)
+        }
+    }
+
+    impl Drop for TempMsh {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn same_as_parsed() {
+        for path in &["props/v2/bin-tetra.msh", "props/v4/square-bin.msh"] {
+            let mesh = MappedMsh::open(path).unwrap();
+            let msh = Msh::from_file(path).unwrap();
+            assert_eq!(mesh.num_nodes(), msh.nodes.len());
+            assert_eq!(mesh.to_msh(), msh, "{}", path);
+        }
+    }
+
+    #[test]
+    fn written_files() {
+        let props = ["props/v2/tetra-pgroup.msh", "props/v2/views.msh", "props/v4/partitioned.msh", "props/v4/parametric.msh"];
+        for (i, path) in props.iter().enumerate() {
+            let msh = Msh::from_file(path).unwrap();
+            for &storage in &[Storage::BinaryLe, Storage::BinaryBe] {
+                let msh2 = TempMsh::new(&format!("{}-2-{:?}", i, storage), |file| msh.write_msh2(file, storage));
+                let msh4 = TempMsh::new(&format!("{}-4-{:?}", i, storage), |file| msh.write_msh4(file, storage));
+                for file in &[msh2, msh4] {
+                    let mesh = MappedMsh::open(&file.0).unwrap();
+                    let read = Msh::from_file(&file.0).unwrap();
+                    assert_eq!(mesh.to_msh(), read, "{} ({:?})", path, storage);
+                    for (i, node) in read.nodes.iter().enumerate() {
+                        assert_eq!(mesh.node(i).as_ref(), Some(node));
+                    }
+                    assert_eq!(mesh.node(read.nodes.len()), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn borrowed_coordinates() {
+        let msh = Msh::from_file("props/v4/square.msh").unwrap();
+        let native = if cfg!(target_endian = "big") { Storage::BinaryBe } else { Storage::BinaryLe };
+        let mut bytes = Vec::new();
+        msh.write_msh4(&mut bytes, native).unwrap();
+        let unaligned = TempMsh::new("unaligned", |file| std::io::Write::write_all(file, &bytes));
+        let tags = match MappedMsh::open(&unaligned.0).unwrap().node_blocks[0].layout {
+            NodeLayout::Split { tags, .. } => tags,
+            NodeLayout::Records { .. } => unreachable!(),
+        };
+        // a comment before the nodes aligns the first block
+        let header_len = bytes.windows(15).position(|window| window == b"$EndMeshFormat\n").unwrap() + 15;
+        let comment = format!("$Comments\n{}\n$EndComments\n", "#".repeat((8 - tags % 8) % 8));
+        bytes.splice(header_len..header_len, comment.bytes());
+        let file = TempMsh::new("borrowed", |file| std::io::Write::write_all(file, &bytes));
+
+        let mesh = MappedMsh::open(&file.0).unwrap();
+        let mut borrowed = 0;
+        for (i, block) in mesh.node_blocks.iter().enumerate() {
+            if let (Some(tags), Some(coords)) = (mesh.node_block_tags(i), mesh.node_block_coordinates(i)) {
+                for (k, (&tag, point)) in tags.iter().zip(coords.chunks(3)).enumerate() {
+                    let node = mesh.node(block.first + k).unwrap();
+                    assert_eq!((tag, point), (node.tag, &[node.x, node.y, node.z][..]));
+                }
+                borrowed += 1;
+            }
+        }
+        assert!(borrowed > 0);
+    }
+
+    #[test]
+    fn ascii() {
+        match MappedMsh::open("props/v4/square.msh") {
+            Err(MshError::NotBinary { version: MshVersion::AsciiV41 }) => (),
+            other => panic!("expected an error for ASCII files, got {:?}", other.map(|mesh| mesh.version())),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = std::fs::read("props/v4/square-bin.msh").unwrap();
+        let nodes = bytes.windows(7).position(|window| window == b"$Nodes\n").unwrap();
+        let file = TempMsh::new("truncated", |file| std::io::Write::write_all(file, &bytes[..nodes + 200]));
+        match MappedMsh::open(&file.0) {
+            Err(MshError::Truncated { location }) => assert_eq!(location.section, Some("$Nodes")),
+            other => panic!("expected a truncated block, got {:?}", other.map(|mesh| mesh.version())),
+        }
+    }
+}
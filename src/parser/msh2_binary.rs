@@ -0,0 +1,279 @@
+//! Binary `msh` 2.2 files.
+//!
+//! Section headers, counts and `$PhysicalNames` are ASCII, while the `$Nodes`
+//! and `$Elements` records are `int`s and `double`s in the file's byte order.
+use super::*;
+use super::encoding::Encoding;
+
+use nom::combinator::{map, value};
+
+use std::convert::TryFrom;
+
+pub(crate) fn parse_msh2_binary<'a, E: Encoding>(input: &'a [u8], diagnostics: &Diagnostics) -> IResult<&'a [u8], Msh> {
+    let (input, header) = msh_header(input)?;
+    let mut msh = Msh::new();
+    let mut visit = Visit::new(&mut msh).check_nodes(diagnostics.check_nodes());
+    visit.header(header);
+    let mut msh_input = input;
+    while let Ok((input, section)) = peek(section_header)(msh_input) {
+        let (rest, _) = add_section::<E>(&mut visit, section, input, diagnostics)?;
+        msh_input = rest;
+    }
+    Ok((msh_input, msh))
+}
+
+/// Hands the section at the start of `input` to `visit`.
+pub(crate) fn parse_section<'a, E: Encoding>(
+    visit: &mut Visit,
+    input: &'a [u8],
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (input, section) = peek(section_header)(input)?;
+    add_section::<E>(visit, section, input, diagnostics)
+}
+
+fn section_header(input: &[u8]) -> IResult<&[u8], Msh2Section> {
+    alt((
+        value(Msh2Section::Nodes, terminated(tag("$Nodes"), eol)),
+        value(Msh2Section::Elements, terminated(tag("$Elements"), eol)),
+        value(Msh2Section::PhysicalGroups, terminated(tag("$PhysicalNames"), eol)),
+        value(Msh2Section::Periodic, terminated(tag("$Periodic"), eol)),
+        value(Msh2Section::InterpolationScheme, terminated(tag("$InterpolationScheme"), eol)),
+        map(data::data_header, Msh2Section::Data),
+        value(Msh2Section::Unknown, unknown_section_header),
+    ))(input)
+}
+
+fn add_section<'a, E: Encoding>(
+    visit: &mut Visit,
+    section: Msh2Section,
+    input: &'a [u8],
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    use Msh2Section::*;
+    match section {
+        Nodes => in_section("$Nodes", parse_nodes::<E>(input, visit)),
+        Elements => in_section("$Elements", parse_elements::<E>(input, visit, diagnostics)),
+        PhysicalGroups => {
+            // physical names are always written as text
+            let pgs = text_section(input, "$EndPhysicalNames", |i| parse_physical_groups_msh2(i, diagnostics));
+            let (rest, pgs) = in_section("$PhysicalNames", pgs)?;
+            pgs.into_iter().for_each(|group| visit.physical_group(group));
+            Ok((rest, ()))
+        }
+        Periodic => {
+            let links = text_section(input, "$EndPeriodic", parse_periodic_msh2);
+            let (rest, links) = in_section("$Periodic", links)?;
+            links.into_iter().for_each(|link| visit.periodic_link(link));
+            Ok((rest, ()))
+        }
+        InterpolationScheme => {
+            // interpolation schemes are always written as text
+            let (rest, scheme) = in_section("$InterpolationScheme", data::parse_interpolation_scheme(input))?;
+            visit.interpolation_scheme(scheme);
+            Ok((rest, ()))
+        }
+        Data(kind) => {
+            let (rest, (name, kind, step)) = in_section(data::section_name(kind), data::parse_data::<E, _>(input, int_tag::<E>))?;
+            visit.data_step(name, kind, step);
+            Ok((rest, ()))
+        }
+        Unknown => {
+            let (rest, section) = parse_unknown_section(input, diagnostics)?;
+            visit.unknown_section(section);
+            Ok((rest, ()))
+        }
+    }
+}
+
+fn parse_nodes<'a, E: Encoding>(input: &'a [u8], visit: &mut Visit) -> IResult<&'a [u8], ()> {
+    let (input, _) = terminated(tag("$Nodes"), eol)(input)?;
+    let (mut input, num_nodes) = cut(ascii_count)(input)?;
+    for _ in 0..num_nodes {
+        let (rest, node) = cut(parse_node::<E>)(input)?;
+        visit.node(node);
+        input = rest;
+    }
+    let (input, _) = cut(section_footer("$EndNodes"))(input)?;
+    Ok((input, ()))
+}
+
+pub(super) fn parse_node<E: Encoding>(input: &[u8]) -> IResult<&[u8], Node> {
+    let (input, tag) = uint::<E>(input)?;
+    let (input, x) = E::double(input)?;
+    let (input, y) = E::double(input)?;
+    let (input, z) = E::double(input)?;
+    Ok((input, Node { tag: tag.into(), x, y, z, entity: None, parametric: None }))
+}
+
+fn parse_elements<'a, E: Encoding>(
+    input: &'a [u8],
+    visit: &mut Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (count_line, _) = terminated(tag("$Elements"), eol)(input)?;
+    let (mut input, num_elts) = cut(ascii_count)(count_line)?;
+    // elements are grouped in blocks sharing a type and number of tags
+    let mut found = 0;
+    while found < num_elts {
+        let (rest, block_len) = cut_result(parse_element_block::<E>(input, visit, diagnostics))?;
+        found += block_len;
+        input = rest;
+    }
+    // the last block may run past the count
+    if found > num_elts {
+        let kind = DiagnosticKind::CountMismatch { expected: num_elts as u64, found: found as u64 };
+        diagnostics.warn(count_line, "$Elements", kind)?;
+    }
+    let (input, _) = cut(section_footer("$EndElements"))(input)?;
+    Ok((input, ()))
+}
+
+/// Returns the number of elements in the block.
+fn parse_element_block<'a, E: Encoding>(
+    input: &'a [u8],
+    visit: &mut Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], usize> {
+    let (mut input, (mut elt, num_elts, num_tags)) = element_block_header::<E>(input)?;
+    // one element and its tags are reused for every record
+    let mut tags = Vec::new();
+    for _ in 0..num_elts {
+        let (rest, _) = parse_element::<E>(input, &mut elt, &mut tags, num_tags, diagnostics)?;
+        check_nodes(input, &elt, visit, diagnostics)?;
+        visit.element(&elt);
+        input = rest;
+    }
+    Ok((input, num_elts))
+}
+
+/// The element the block's records are read into, and their number and number of tags.
+pub(super) fn element_block_header<E: Encoding>(input: &[u8]) -> IResult<&[u8], (MeshElt, usize, usize)> {
+    let (rest, label) = E::int(input)?;
+    let (ty, num_nodes) = match u32::try_from(label).ok().and_then(MeshShape::from_gmsh_type).and_then(sized) {
+        Some(ty) => ty,
+        None => return fail(input, ParseErrorKind::ElementType(label.into())),
+    };
+    let (input, num_elts) = uint::<E>(rest)?;
+    let (input, num_tags) = uint::<E>(input)?;
+    let elt = MeshElt::new(0, ty, Vec::with_capacity(num_nodes));
+    Ok((input, (elt, num_elts as usize, num_tags as usize)))
+}
+
+/// Reads a record into `elt`, which has the block's type, using `tags` for its tags.
+pub(super) fn parse_element<'a, E: Encoding>(
+    input: &'a [u8],
+    elt: &mut MeshElt,
+    tags: &mut Vec<i64>,
+    num_tags: usize,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let num_nodes = elt.ty.num_nodes().unwrap_or_default();
+    let (tags_start, tag) = uint::<E>(input)?;
+    // ghost partitions are negative
+    let (input, _) = count_into(tags_start, map(E::int, i64::from), num_tags, tags)?;
+    let (input, _) = count_into(input, int_tag::<E>, num_nodes, &mut elt.nodes)?;
+    let elt_info = EltInfo::from_tags(tags);
+    if elt_info.num_ignored > 0 {
+        diagnostics.warn_once(tags_start, "$Elements", DiagnosticKind::IgnoredElementTags)?;
+    }
+    elt_info.fill(tag.into(), elt.ty, elt);
+    Ok((input, ()))
+}
+
+fn uint<E: Encoding>(input: &[u8]) -> IResult<&[u8], u32> {
+    map_res(E::int, u32::try_from)(input)
+}
+
+fn int_tag<E: Encoding>(input: &[u8]) -> IResult<&[u8], Tag> {
+    let (input, tag) = uint::<E>(input)?;
+    Ok((input, tag.into()))
+}
+
+pub(super) fn ascii_count(input: &[u8]) -> IResult<&[u8], usize> {
+    terminated(map_res(map_res(digit1, std::str::from_utf8), usize::from_str), eol)(input)
+}
+
+/// Binary records are followed by a newline before the closing tag.
+pub(super) fn section_footer<'a>(end: &'static str) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], ()> {
+    move |input| {
+        let (input, _) = line_ending(input)?;
+        let (input, _) = terminated(tag(end), eol)(input)?;
+        Ok((input, ()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_tetra_matches_ascii() {
+        let bin = std::fs::read("props/v2/bin-tetra.msh").unwrap();
+        let ascii = std::fs::read_to_string("props/v2/tetra.msh").unwrap();
+        let (rest, bin_msh) = parse_msh2_binary::<BinaryLe>(&bin, &Diagnostics::lenient(&bin)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(bin_msh.nodes.len(), 14);
+        assert_eq!(bin_msh.elts.len(), 68);
+        assert_eq!(bin_msh, parse_msh2_ascii(&ascii, &Diagnostics::lenient(ascii.as_bytes())).unwrap().1);
+    }
+
+    #[test]
+    fn empty_binary() {
+        let bin = std::fs::read("props/v2/empty-bin.msh").unwrap();
+        assert_eq!(parse_msh_file(&bin).unwrap(), vec![Msh::new()]);
+    }
+
+    #[test]
+    fn unknown_element_type() {
+        let mut input = b"$Elements\n1\n".to_vec();
+        for int in &[999u32, 1, 0, 1, 1] {
+            input.extend_from_slice(&int.to_le_bytes());
+        }
+        input.extend_from_slice(b"\n$EndElements\n");
+        match parse_elements::<BinaryLe>(&input, &mut Visit::new(&mut Msh::new()), &Diagnostics::lenient(&input)) {
+            Err(Err::Failure(err)) => assert_eq!(err.kind, ParseErrorKind::ElementType(999)),
+            other => panic!("expected an element type error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_geometry() {
+        let mut input = b"$Elements\n1\n".to_vec();
+        for int in &[15, 1, 2, 1, 4, -1, 1] {
+            input.extend_from_slice(&i32::to_le_bytes(*int));
+        }
+        input.extend_from_slice(b"\n$EndElements\n");
+        let diagnostics = Diagnostics::lenient(&input);
+        parse_elements::<BinaryLe>(&input, &mut Visit::new(&mut Msh::new()), &diagnostics).unwrap();
+        let kinds: Vec<_> = diagnostics.into_vec().into_iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec![DiagnosticKind::IgnoredElementTags]);
+    }
+
+    #[test]
+    fn block_past_count() {
+        // one block of two points under a count of one
+        let mut input = b"$Elements\n1\n".to_vec();
+        for int in &[15u32, 2, 0, 1, 1, 2, 1] {
+            input.extend_from_slice(&int.to_le_bytes());
+        }
+        input.extend_from_slice(b"\n$EndElements\n");
+        let mut msh = Msh::new();
+        let mut visit = Visit::new(&mut msh);
+        visit.node(Node { tag: 1, x: 0.0, y: 0.0, z: 0.0, entity: None, parametric: None });
+        let diagnostics = Diagnostics::lenient(&input);
+        parse_elements::<BinaryLe>(&input, &mut visit, &diagnostics).unwrap();
+        let kinds: Vec<_> = diagnostics.into_vec().into_iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec![DiagnosticKind::CountMismatch { expected: 1, found: 2 }]);
+        assert_eq!(msh.elts.len(), 2);
+
+        let mut msh = Msh::new();
+        let mut visit = Visit::new(&mut msh);
+        visit.node(Node { tag: 1, x: 0.0, y: 0.0, z: 0.0, entity: None, parametric: None });
+        let strict = Diagnostics::new(&input, &ReadOptions { strict: true, ..ReadOptions::default() });
+        match parse_elements::<BinaryLe>(&input, &mut visit, &strict) {
+            Err(Err::Failure(err)) => assert_eq!(err.kind, ParseErrorKind::Count { expected: 1, found: 2 }),
+            other => panic!("expected a count error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,531 @@
+//! `msh` 4.1 files.
+//!
+//! ASCII and binary 4.1 files share one layout and only differ in how numbers
+//! are encoded, so the section parsers are generic over an [`Encoding`].
+//!
+//! [`Encoding`]: super::encoding::Encoding
+use super::*;
+use super::encoding::Encoding;
+
+use nom::character::complete::multispace0;
+use nom::combinator::{map, map_opt, value};
+use nom::sequence::{pair, preceded, tuple};
+
+use std::convert::TryFrom;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Section {
+    Entities,
+    PartitionedEntities,
+    Nodes,
+    Elements,
+    PhysicalGroups,
+    Periodic,
+    Parametrizations,
+    InterpolationScheme,
+    Data(DataKind),
+    Unknown,
+}
+
+pub(crate) fn parse_msh4<'a, E: Encoding>(input: &'a [u8], diagnostics: &Diagnostics) -> IResult<&'a [u8], Msh> {
+    let (input, header) = msh_header(input)?;
+    let mut msh = Msh::new();
+    let mut visit = Visit::new(&mut msh).check_nodes(diagnostics.check_nodes());
+    visit.header(header);
+    let mut msh_input = input;
+    while let Ok((input, section)) = peek(section_header)(msh_input) {
+        let (rest, _) = add_section::<E>(&mut visit, section, input, diagnostics)?;
+        msh_input = rest;
+    }
+    Ok((msh_input, msh))
+}
+
+/// Hands the section at the start of `input` to `visit`.
+pub(crate) fn parse_section<'a, E: Encoding>(
+    visit: &mut Visit,
+    input: &'a [u8],
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (input, section) = peek(section_header)(input)?;
+    add_section::<E>(visit, section, input, diagnostics)
+}
+
+fn section_header(input: &[u8]) -> IResult<&[u8], Section> {
+    alt((
+        value(Section::Entities, terminated(tag("$Entities"), eol)),
+        value(Section::PartitionedEntities, terminated(tag("$PartitionedEntities"), eol)),
+        value(Section::Nodes, terminated(tag("$Nodes"), eol)),
+        value(Section::Elements, terminated(tag("$Elements"), eol)),
+        value(Section::PhysicalGroups, terminated(tag("$PhysicalNames"), eol)),
+        value(Section::Periodic, terminated(tag("$Periodic"), eol)),
+        value(Section::Parametrizations, terminated(tag("$Parametrizations"), eol)),
+        value(Section::InterpolationScheme, terminated(tag("$InterpolationScheme"), eol)),
+        map(data::data_header, Section::Data),
+        value(Section::Unknown, unknown_section_header),
+    ))(input)
+}
+
+fn add_section<'a, E: Encoding>(
+    visit: &mut Visit,
+    section: Section,
+    input: &'a [u8],
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    use Section::*;
+    match section {
+        Entities => {
+            let (rest, entities) = in_section("$Entities", parse_entities::<E>(input))?;
+            entities.into_iter().for_each(|entity| visit.entity(entity));
+            Ok((rest, ()))
+        }
+        PartitionedEntities => {
+            let entities = parse_partitioned_entities::<E>(input);
+            let (rest, (entities, partitioning)) = in_section("$PartitionedEntities", entities)?;
+            entities.into_iter().for_each(|entity| visit.entity(entity));
+            visit.partitioning(partitioning);
+            Ok((rest, ()))
+        }
+        Nodes => in_section("$Nodes", parse_nodes::<E>(input, visit, diagnostics)),
+        Elements => in_section("$Elements", parse_elements::<E>(input, visit, diagnostics)),
+        PhysicalGroups => {
+            // physical names are always written as text
+            let pgs = text_section(input, "$EndPhysicalNames", |i| parse_physical_groups_msh2(i, diagnostics));
+            let (rest, pgs) = in_section("$PhysicalNames", pgs)?;
+            pgs.into_iter().for_each(|group| visit.physical_group(group));
+            Ok((rest, ()))
+        }
+        Periodic => {
+            let (rest, links) = in_section("$Periodic", parse_periodic::<E>(input))?;
+            links.into_iter().for_each(|link| visit.periodic_link(link));
+            Ok((rest, ()))
+        }
+        Parametrizations => {
+            let (rest, parametrizations) = in_section("$Parametrizations", parse_parametrizations::<E>(input))?;
+            visit.parametrizations(parametrizations);
+            Ok((rest, ()))
+        }
+        InterpolationScheme => {
+            // interpolation schemes are always written as text
+            let (rest, scheme) = in_section("$InterpolationScheme", data::parse_interpolation_scheme(input))?;
+            visit.interpolation_scheme(scheme);
+            Ok((rest, ()))
+        }
+        Data(kind) => {
+            let (rest, (name, kind, step)) = in_section(data::section_name(kind), data::parse_data::<E, _>(input, E::size_t))?;
+            visit.data_step(name, kind, step);
+            Ok((rest, ()))
+        }
+        Unknown => {
+            let (rest, section) = parse_unknown_section(input, diagnostics)?;
+            visit.unknown_section(section);
+            Ok((rest, ()))
+        }
+    }
+}
+
+/// Closing tag, preceded by the newline binary data ends with.
+pub(super) fn section_footer<'a>(end: &'static str) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], ()> {
+    move |input| {
+        let (input, _) = preceded(multispace0, terminated(tag(end), eol))(input)?;
+        Ok((input, ()))
+    }
+}
+
+pub(super) fn int_tag<E: Encoding>(input: &[u8]) -> IResult<&[u8], Tag> {
+    map_res(E::int, Tag::try_from)(input)
+}
+
+fn signed_tag<E: Encoding>(input: &[u8]) -> IResult<&[u8], i64> {
+    let (input, tag) = E::int(input)?;
+    Ok((input, tag.into()))
+}
+
+pub(super) fn dimension<E: Encoding>(input: &[u8]) -> IResult<&[u8], Dim> {
+    map_opt(E::int, |dim| u8::try_from(dim).ok().and_then(Dim::new))(input)
+}
+
+fn point<E: Encoding>(input: &[u8]) -> IResult<&[u8], [f64; 3]> {
+    let (input, (x, y, z)) = tuple((E::double, E::double, E::double))(input)?;
+    Ok((input, [x, y, z]))
+}
+
+/// Counts come from the file, so they can't be trusted for preallocation.
+fn len(count: u64) -> usize {
+    usize::try_from(count).unwrap_or(usize::MAX)
+}
+
+fn parse_entities<E: Encoding>(input: &[u8]) -> IResult<&[u8], Vec<Entity>> {
+    let (input, _) = terminated(tag("$Entities"), eol)(input)?;
+    let (mut input, counts) = cut(count(E::size_t, 4))(input)?;
+    let mut entities = Vec::new();
+    for (dim, &num_entities) in counts.iter().enumerate() {
+        let dim = Dim::from_u8_unchecked(dim as u8);
+        let (rest, block) = cut(count(move |i| parse_entity::<E>(i, dim), len(num_entities)))(input)?;
+        entities.extend(block);
+        input = rest;
+    }
+    let (input, _) = cut(section_footer("$EndEntities"))(input)?;
+    Ok((input, entities))
+}
+
+fn parse_entity<E: Encoding>(input: &[u8], dim: Dim) -> IResult<&[u8], Entity> {
+    let (input, tag) = int_tag::<E>(input)?;
+    parse_entity_body::<E>(input, dim, tag)
+}
+
+/// An entity's bounding box, physical groups and boundary.
+fn parse_entity_body<E: Encoding>(input: &[u8], dim: Dim, tag: Tag) -> IResult<&[u8], Entity> {
+    let (input, (min, max)) = if dim.as_u8() == 0 {
+        let (input, coords) = point::<E>(input)?;
+        (input, (coords, coords))
+    } else {
+        tuple((point::<E>, point::<E>))(input)?
+    };
+    let (input, num_physicals) = E::size_t(input)?;
+    let (input, physical_groups) = count(int_tag::<E>, len(num_physicals))(input)?;
+    let (input, boundary) = if dim.as_u8() == 0 {
+        (input, Vec::new())
+    } else {
+        let (input, num_bounding) = E::size_t(input)?;
+        count(signed_tag::<E>, len(num_bounding))(input)?
+    };
+    Ok((input, Entity { dim, tag, min, max, physical_groups, boundary }))
+}
+
+fn parse_partitioned_entities<E: Encoding>(input: &[u8]) -> IResult<&[u8], (Vec<Entity>, Partitioning)> {
+    let (input, _) = terminated(tag("$PartitionedEntities"), eol)(input)?;
+    let (input, num_partitions) = cut(E::size_t)(input)?;
+    let (input, num_ghosts) = cut(E::size_t)(input)?;
+    let ghost = map(pair(int_tag::<E>, int_tag::<E>), |(tag, partition)| GhostEntity { tag, partition });
+    let (input, ghost_entities) = cut(count(ghost, len(num_ghosts)))(input)?;
+    let (mut input, counts) = cut(count(E::size_t, 4))(input)?;
+    let mut partitioning = Partitioning { num_partitions, ghost_entities, entities: Vec::new() };
+    let mut entities = Vec::new();
+    for (dim, &num_entities) in counts.iter().enumerate() {
+        let dim = Dim::from_u8_unchecked(dim as u8);
+        let (rest, block) = cut(count(move |i| parse_partitioned_entity::<E>(i, dim), len(num_entities)))(input)?;
+        for (entity, partitioned) in block {
+            entities.push(entity);
+            partitioning.entities.push(partitioned);
+        }
+        input = rest;
+    }
+    let (input, _) = cut(section_footer("$EndPartitionedEntities"))(input)?;
+    Ok((input, (entities, partitioning)))
+}
+
+/// Like an entity, with its parent and partitions between its tag and bounding box.
+fn parse_partitioned_entity<E: Encoding>(input: &[u8], dim: Dim) -> IResult<&[u8], (Entity, PartitionedEntity)> {
+    let (input, tag) = int_tag::<E>(input)?;
+    let (input, parent_dim) = dimension::<E>(input)?;
+    let (input, parent_tag) = int_tag::<E>(input)?;
+    let (input, num_partitions) = E::size_t(input)?;
+    let (input, partitions) = count(int_tag::<E>, len(num_partitions))(input)?;
+    let (input, entity) = parse_entity_body::<E>(input, dim, tag)?;
+    let parent = EntityId { dim: parent_dim, tag: parent_tag };
+    Ok((input, (entity, PartitionedEntity { id: EntityId { dim, tag }, parent, partitions })))
+}
+
+fn parse_nodes<'a, E: Encoding>(
+    input: &'a [u8],
+    visit: &mut Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (count_line, _) = terminated(tag("$Nodes"), eol)(input)?;
+    let (mut input, (num_blocks, num_nodes)) = cut(section_counts::<E>)(count_line)?;
+    let mut found = 0;
+    for _ in 0..num_blocks {
+        let (rest, block_len) = cut_result(parse_node_block::<E>(input, visit))?;
+        found += block_len;
+        input = rest;
+    }
+    if found != num_nodes {
+        let kind = DiagnosticKind::CountMismatch { expected: num_nodes, found };
+        diagnostics.warn(count_line, "$Nodes", kind)?;
+    }
+    let (input, _) = cut(section_footer("$EndNodes"))(input)?;
+    Ok((input, ()))
+}
+
+/// The number of blocks and entries of a `$Nodes` or `$Elements` section.
+pub(super) fn section_counts<E: Encoding>(input: &[u8]) -> IResult<&[u8], (u64, u64)> {
+    // min and max tags are meaningless sentinels in empty sections, so skip them
+    let (input, (num_blocks, num_entries, _, _)) = tuple((E::size_t, E::size_t, E::size_t, E::size_t))(input)?;
+    Ok((input, (num_blocks, num_entries)))
+}
+
+/// Returns the number of nodes in the block.
+fn parse_node_block<'a, E: Encoding>(input: &'a [u8], visit: &mut Visit) -> IResult<&'a [u8], u64> {
+    let (input, (entity, parametric, num_nodes)) = node_block_header::<E>(input)?;
+    // all tags come first, followed by all coordinates
+    let (mut input, tags) = count(E::size_t, len(num_nodes))(input)?;
+    for &tag in &tags {
+        let (rest, node) = parse_node::<E>(input, entity, parametric)?;
+        visit.node(Node { tag, ..node });
+        input = rest;
+    }
+    Ok((input, tags.len() as u64))
+}
+
+/// The entity of a block of nodes, whether they're parametric, and their number.
+pub(super) fn node_block_header<E: Encoding>(input: &[u8]) -> IResult<&[u8], (EntityId, bool, u64)> {
+    let (input, dim) = dimension::<E>(input)?;
+    let (input, tag) = int_tag::<E>(input)?;
+    let (input, parametric) = E::int(input)?;
+    let (input, num_nodes) = E::size_t(input)?;
+    Ok((input, (EntityId { dim, tag }, parametric != 0, num_nodes)))
+}
+
+/// A node's coordinates, followed by as many parametric ones as its entity has
+/// dimensions. Its tag comes earlier in the block, so it's left at zero.
+pub(super) fn parse_node<E: Encoding>(input: &[u8], entity: EntityId, parametric: bool) -> IResult<&[u8], Node> {
+    let (input, [x, y, z]) = point::<E>(input)?;
+    let mut node = Node { tag: 0, x, y, z, entity: Some(entity), parametric: None };
+    if !parametric {
+        return Ok((input, node));
+    }
+    let num_params = entity.dim.as_u8() as usize;
+    let (input, params) = count(E::double, num_params)(input)?;
+    let mut coords = [0.0; 3];
+    coords[..num_params].copy_from_slice(&params);
+    node.parametric = Some(coords);
+    Ok((input, node))
+}
+
+fn parse_elements<'a, E: Encoding>(
+    input: &'a [u8],
+    visit: &mut Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], ()> {
+    let (count_line, _) = terminated(tag("$Elements"), eol)(input)?;
+    let (mut input, (num_blocks, num_elts)) = cut(section_counts::<E>)(count_line)?;
+    let mut found = 0;
+    for _ in 0..num_blocks {
+        let (rest, block_len) = cut_result(parse_element_block::<E>(input, visit, diagnostics))?;
+        found += block_len;
+        input = rest;
+    }
+    if found != num_elts {
+        let kind = DiagnosticKind::CountMismatch { expected: num_elts, found };
+        diagnostics.warn(count_line, "$Elements", kind)?;
+    }
+    let (input, _) = cut(section_footer("$EndElements"))(input)?;
+    Ok((input, ()))
+}
+
+/// Returns the number of elements in the block.
+fn parse_element_block<'a, E: Encoding>(
+    input: &'a [u8],
+    visit: &mut Visit,
+    diagnostics: &Diagnostics,
+) -> IResult<&'a [u8], u64> {
+    let (mut input, (mut elt, num_elts)) = element_block_header::<E>(input, visit)?;
+    for _ in 0..num_elts {
+        let (rest, _) = parse_element::<E>(input, &mut elt)?;
+        check_nodes(input, &elt, visit, diagnostics)?;
+        visit.element(&elt);
+        input = rest;
+    }
+    Ok((input, num_elts))
+}
+
+/// The element the block's records are read into, taking the physical groups
+/// of its entity, and their number.
+pub(super) fn element_block_header<'a, E: Encoding>(
+    input: &'a [u8],
+    visit: &Visit,
+) -> IResult<&'a [u8], (MeshElt, u64)> {
+    let (input, dim) = dimension::<E>(input)?;
+    let (input, entity_tag) = int_tag::<E>(input)?;
+    let (rest, label) = E::int(input)?;
+    let (ty, num_nodes) = match u32::try_from(label).ok().and_then(MeshShape::from_gmsh_type).and_then(sized) {
+        Some(ty) => ty,
+        None => return fail(input, ParseErrorKind::ElementType(label.into())),
+    };
+    let (input, num_elts) = E::size_t(rest)?;
+    let elt = MeshElt {
+        physical_groups: visit.entity_groups(EntityId { dim, tag: entity_tag }).to_vec(),
+        geometry: Some(entity_tag),
+        ..MeshElt::new(0, ty, Vec::with_capacity(num_nodes))
+    };
+    Ok((input, (elt, num_elts)))
+}
+
+/// Reads a record into `elt`, which has the block's type.
+pub(super) fn parse_element<'a, E: Encoding>(input: &'a [u8], elt: &mut MeshElt) -> IResult<&'a [u8], ()> {
+    let num_nodes = elt.ty.num_nodes().unwrap_or_default();
+    let (input, tag) = E::size_t(input)?;
+    let (input, _) = count_into(input, E::size_t, num_nodes, &mut elt.nodes)?;
+    elt.tag = tag;
+    Ok((input, ()))
+}
+
+fn parse_periodic<E: Encoding>(input: &[u8]) -> IResult<&[u8], Vec<PeriodicLink>> {
+    let (input, _) = terminated(tag("$Periodic"), eol)(input)?;
+    let (input, num_links) = cut(E::size_t)(input)?;
+    let (input, links) = cut(count(parse_periodic_link::<E>, len(num_links)))(input)?;
+    let (input, _) = cut(section_footer("$EndPeriodic"))(input)?;
+    Ok((input, links))
+}
+
+fn parse_periodic_link<E: Encoding>(input: &[u8]) -> IResult<&[u8], PeriodicLink> {
+    let (input, dim) = dimension::<E>(input)?;
+    let (input, entity) = int_tag::<E>(input)?;
+    let (input, master) = int_tag::<E>(input)?;
+    let (affine_start, num_affine) = E::size_t(input)?;
+    let (input, affine) = count(E::double, len(num_affine))(affine_start)?;
+    let affine = match affine.len() {
+        0 => None,
+        16 => Some(affine_matrix(&affine)),
+        _ => return fail(affine_start, ParseErrorKind::Nom(nom::error::ErrorKind::Verify)),
+    };
+    let (input, num_nodes) = E::size_t(input)?;
+    let (input, nodes) = count(pair(E::size_t, E::size_t), len(num_nodes))(input)?;
+    Ok((input, PeriodicLink { dim, entity, master, affine, nodes }))
+}
+
+fn parse_parametrizations<E: Encoding>(input: &[u8]) -> IResult<&[u8], Parametrizations> {
+    let (input, _) = terminated(tag("$Parametrizations"), eol)(input)?;
+    let (input, (num_curves, num_surfaces)) = cut(pair(E::size_t, E::size_t))(input)?;
+    let (input, curves) = cut(count(parse_curve_parametrization::<E>, len(num_curves)))(input)?;
+    let (input, surfaces) = cut(count(parse_surface_parametrization::<E>, len(num_surfaces)))(input)?;
+    let (input, _) = cut(section_footer("$EndParametrizations"))(input)?;
+    Ok((input, Parametrizations { curves, surfaces }))
+}
+
+fn parse_curve_parametrization<E: Encoding>(input: &[u8]) -> IResult<&[u8], CurveParametrization> {
+    let (input, tag) = int_tag::<E>(input)?;
+    let (input, num_nodes) = E::size_t(input)?;
+    let node = map(tuple((E::double, E::double, E::double, E::double)), |(x, y, z, u)| [x, y, z, u]);
+    let (input, nodes) = count(node, len(num_nodes))(input)?;
+    Ok((input, CurveParametrization { tag, nodes }))
+}
+
+fn parse_surface_parametrization<E: Encoding>(input: &[u8]) -> IResult<&[u8], SurfaceParametrization> {
+    let (input, tag) = int_tag::<E>(input)?;
+    let (input, (num_nodes, num_triangles)) = pair(E::size_t, E::size_t)(input)?;
+    let (input, nodes) = count(parse_surface_parametrization_node::<E>, len(num_nodes))(input)?;
+    let index = |input| map_res(E::int, usize::try_from)(input);
+    let triangle = map(tuple((index, index, index)), |(a, b, c)| [a, b, c]);
+    let (input, triangles) = count(triangle, len(num_triangles))(input)?;
+    Ok((input, SurfaceParametrization { tag, nodes, triangles }))
+}
+
+fn parse_surface_parametrization_node<E: Encoding>(input: &[u8]) -> IResult<&[u8], SurfaceParametrizationNode> {
+    let (input, coords) = point::<E>(input)?;
+    let (input, (u, v)) = pair(E::double, E::double)(input)?;
+    let (input, (curvature_max, curvature_min)) = pair(point::<E>, point::<E>)(input)?;
+    Ok((input, SurfaceParametrizationNode { point: coords, uv: [u, v], curvature_max, curvature_min }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_debug_snapshot;
+
+    #[test]
+    fn square() {
+        let msh = std::fs::read("props/v4/square.msh").unwrap();
+        let (rest, msh) = parse_msh4::<Ascii>(&msh, &Diagnostics::lenient(&msh)).unwrap();
+        assert!(rest.is_empty());
+        assert_debug_snapshot!(msh);
+    }
+
+    #[test]
+    fn empty() {
+        let msh = std::fs::read("props/v4/empty.msh").unwrap();
+        let meshes = parse_msh_file(&msh).unwrap();
+        assert_eq!(meshes, vec![Msh::new()]);
+    }
+
+    #[test]
+    fn binary_square_matches_ascii() {
+        let bin = std::fs::read("props/v4/square-bin.msh").unwrap();
+        let ascii = std::fs::read("props/v4/square.msh").unwrap();
+        let (rest, bin_msh) = parse_msh4::<BinaryLe>(&bin, &Diagnostics::lenient(&bin)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(bin_msh, parse_msh4::<Ascii>(&ascii, &Diagnostics::lenient(&ascii)).unwrap().1);
+    }
+
+    #[test]
+    fn empty_binary() {
+        // empty sections store the maximum `size_t` as their min tag
+        let msh = std::fs::read("props/v4/empty-bin.msh").unwrap();
+        let meshes = parse_msh_file(&msh).unwrap();
+        assert_eq!(meshes, vec![Msh::new()]);
+    }
+
+    #[test]
+    fn truncated_binary_nodes() {
+        let bin = std::fs::read("props/v4/square-bin.msh").unwrap();
+        let start = bin.windows(7).position(|w| w == b"$Nodes\n").unwrap();
+        let diagnostics = Diagnostics::lenient(&bin);
+        assert!(parse_nodes::<BinaryLe>(&bin[start..start + 100], &mut Visit::new(&mut Msh::new()), &diagnostics).is_err());
+    }
+
+    #[test]
+    fn node_block_tags_then_coordinates() {
+        let mut msh = Msh::new();
+        parse_node_block::<Ascii>(b"1 3 0 2\n7\n8\n0 0 1\n0.5 0 1\n", &mut Visit::new(&mut msh)).unwrap();
+        let nodes = msh.nodes;
+        assert_eq!(nodes.iter().map(|node| node.tag).collect::<Vec<_>>(), vec![7, 8]);
+        assert_eq!(nodes[1].x, 0.5);
+        assert_eq!(nodes[1].entity, Some(EntityId { dim: Dim::from_u8_unchecked(1), tag: 3 }));
+    }
+
+    #[test]
+    fn nodes_count_mismatch() {
+        let input = b"$Nodes\n1 2 1 2\n0 1 0 1\n1\n0 0 0\n$EndNodes\n";
+        let mut msh = Msh::new();
+        let diagnostics = Diagnostics::lenient(input);
+        let (rest, _) = parse_nodes::<Ascii>(input, &mut Visit::new(&mut msh), &diagnostics).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(msh.nodes.len(), 1);
+        let kinds: Vec<_> = diagnostics.into_vec().into_iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec![DiagnosticKind::CountMismatch { expected: 2, found: 1 }]);
+    }
+
+    #[test]
+    fn periodic_link() {
+        let input = b"$Periodic\n1\n1 3 1\n0\n2\n4 1\n3 2\n$EndPeriodic\n";
+        let (rest, links) = parse_periodic::<Ascii>(input).unwrap();
+        assert!(rest.is_empty());
+        assert_debug_snapshot!(links);
+    }
+
+    #[test]
+    fn partial_affine() {
+        let input = b"$Periodic\n1\n1 3 1\n2 1 0\n0\n$EndPeriodic\n";
+        assert!(parse_periodic::<Ascii>(input).is_err());
+    }
+
+    #[test]
+    fn partitioned_round_trip() {
+        let input = std::fs::read("props/v4/partitioned.msh").unwrap();
+        let (_, msh) = parse_msh4::<Ascii>(&input, &Diagnostics::lenient(&input)).unwrap();
+        let mut buffer = Vec::new();
+        msh.write_msh4(&mut buffer, Storage::Ascii).unwrap();
+        assert_eq!(buffer, input);
+    }
+
+    #[test]
+    fn parametric_nodes() {
+        let input = std::fs::read("props/v4/parametric.msh").unwrap();
+        let (rest, msh) = parse_msh4::<Ascii>(&input, &Diagnostics::lenient(&input)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(msh.nodes[0].parametric, None);
+        assert_eq!(msh.nodes[2].parametric, Some([0.5, 0.0, 0.0]));
+        assert_eq!(msh.nodes[3].parametric, Some([0.25, 0.5, 0.0]));
+        assert_eq!(msh.parametrizations.curves[0].nodes[1], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(msh.parametrizations.surfaces[0].nodes[1].uv, [1.0, 0.0]);
+        assert_eq!(msh.parametrizations.surfaces[0].triangles, vec![[0, 1, 2]]);
+        let mut buffer = Vec::new();
+        msh.write_msh4(&mut buffer, Storage::Ascii).unwrap();
+        assert_eq!(buffer, input);
+        for &storage in &[Storage::BinaryLe, Storage::BinaryBe] {
+            let mut buffer = Vec::new();
+            msh.write_msh4(&mut buffer, storage).unwrap();
+            assert_eq!(Msh::read(buffer.as_slice()).unwrap(), msh);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Parsing the records of large ASCII sections on several threads.
+use super::*;
+
+use rayon::prelude::*;
+
+/// Sections smaller than this aren't worth splitting.
+const MIN_CHUNK: usize = 1 << 16;
+
+/// Parses the records between `input` and the closing line `end`, splitting
+/// them into line-aligned chunks that are parsed concurrently.
+///
+/// Returns the input after the closing line and the chunks in order, or `None`
+/// if anything looks off, in which case the section should be parsed serially
+/// to get the same records or error as usual. Nothing is handed on until every
+/// chunk has been read.
+pub(super) fn records<'a, 'd, T, F>(
+    input: &'a str,
+    end: &'static str,
+    diagnostics: &Diagnostics<'d>,
+    record: F,
+) -> Option<(&'a str, Vec<Chunk<'a, T>>)>
+where
+    T: Send,
+    F: Fn(&'a str, &Diagnostics<'d>) -> IResult<&'a str, T> + Sync,
+{
+    if !diagnostics.parallel() {
+        return None;
+    }
+    let body_len = closing_line(input, end)?;
+    let (rest, _) = terminated(tag(end), end_of_line)(&input[body_len..]).ok()?;
+    let body = &input[..body_len];
+    // a few chunks per thread, so that threads finishing early can take more
+    let chunk_len = MIN_CHUNK.max(body.len() / (4 * rayon::current_num_threads()));
+    if body.len() <= chunk_len {
+        return None;
+    }
+
+    let jobs: Vec<_> = split(body, chunk_len).into_iter().map(|chunk| (chunk, diagnostics.fork())).collect();
+    let parsed: Vec<_> = jobs
+        .into_par_iter()
+        .map(|(mut chunk, diagnostics)| {
+            let mut records = Vec::new();
+            while !chunk.is_empty() {
+                let (rest, item) = record(chunk, &diagnostics).ok()?;
+                // errors are located by the input left, so records start with the rest of the section
+                let offset = chunk.as_ptr() as usize - input.as_ptr() as usize;
+                records.push((&input[offset..], item));
+                chunk = rest;
+            }
+            Some((records, diagnostics))
+        })
+        .collect();
+
+    let mut chunks = Vec::with_capacity(parsed.len());
+    let mut forks = Vec::with_capacity(parsed.len());
+    for (records, fork) in parsed.into_iter().collect::<Option<Vec<_>>>()? {
+        chunks.push(records);
+        forks.push(fork);
+    }
+    forks.into_iter().for_each(|fork| diagnostics.merge_once(fork));
+    Some((rest, chunks))
+}
+
+/// Where the first line starting with `end` starts.
+fn closing_line(input: &str, end: &str) -> Option<usize> {
+    if input.starts_with(end) {
+        return Some(0);
+    }
+    input.find(&format!("\n{}", end)).map(|at| at + 1)
+}
+
+/// Cuts `body` into pieces of about `chunk_len` bytes, each starting a line
+/// that doesn't start with whitespace.
+///
+/// Records are read up to the whitespace following them, so a record starts
+/// wherever such a line does.
+fn split(mut body: &str, chunk_len: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    while body.len() > chunk_len {
+        let bytes = body.as_bytes();
+        let boundary = (chunk_len..bytes.len() - 1)
+            .find(|&at| bytes[at] == b'\n' && !bytes[at + 1].is_ascii_whitespace());
+        match boundary {
+            Some(at) => {
+                let (chunk, rest) = body.split_at(at + 1);
+                chunks.push(chunk);
+                body = rest;
+            }
+            None => break,
+        }
+    }
+    chunks.push(body);
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parallel() -> ReadOptions {
+        ReadOptions { parallel: true, ..ReadOptions::default() }
+    }
+
+    /// A 2.2 ASCII file large enough to be split.
+    fn large_msh() -> String {
+        let mut msh = String::from("$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n8000\n");
+        for tag in 1..=8000 {
+            msh += &format!("{} {} {}.5 -{}e-3\n", tag, tag % 7, tag, tag);
+        }
+        msh += "$EndNodes\n$Elements\n7999\n";
+        for tag in 1..8000 {
+            msh += &format!("{} 1 2 {} 1 {} {}\n", tag, tag % 3, tag, tag + 1);
+        }
+        msh += "$EndElements\n";
+        msh
+    }
+
+    #[test]
+    fn split_lines() {
+        let body = "1 0 0 0\n2 0 0 0\n  3 0 0 0\n\n4 0 0 0\n";
+        assert_eq!(split(body, 4), vec!["1 0 0 0\n", "2 0 0 0\n  3 0 0 0\n\n", "4 0 0 0\n"]);
+        assert_eq!(split(body, 100), vec![body]);
+    }
+
+    #[test]
+    fn same_as_serial() {
+        let msh = large_msh();
+        let serial = parse_msh_file(&msh).unwrap();
+        let (parallel, diagnostics) = parse_msh_file_with(&msh, &parallel()).unwrap();
+        assert_eq!(parallel, serial);
+        assert!(diagnostics.is_empty());
+        assert_eq!(parallel[0].nodes.len(), 8000);
+    }
+
+    #[test]
+    fn same_diagnostics() {
+        // extra element tags and a wrong count
+        let msh = large_msh()
+            .replace("\n7999\n", "\n8000\n")
+            .replace("\n3000 1 2 0 1", "\n3000 1 6 0 1 0 7 8 9")
+            .replace("\n6000 1 2 0 1", "\n6000 1 6 0 1 0 7 8 9");
+        let (serial, expected) = parse_msh_file_with(&msh, &ReadOptions::default()).unwrap();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(parse_msh_file_with(&msh, &parallel()).unwrap(), (serial, expected));
+    }
+
+    #[test]
+    fn same_results_on_odd_input() {
+        let bad = [
+            // a record split over two lines, which the serial parser reads
+            large_msh().replace("\n1234 ", "\n1234\n"),
+            large_msh().replace("\n7000 ", "\nx "),
+            large_msh().replace("\n5 1 2 2 1 5 6\n", "\n5 1 2 2 1 5 60000\n"),
+            large_msh().replace("$EndElements", "$EndElement"),
+        ];
+        for msh in &bad {
+            let serial = parse_msh_file(msh).map_err(|err| err.to_string());
+            let parallel = parse_msh_file_with(msh, &parallel()).map(|(msh, _)| msh).map_err(|err| err.to_string());
+            assert_eq!(parallel, serial);
+        }
+    }
+}
@@ -0,0 +1,633 @@
+//! Reading `msh` files a record at a time.
+use super::*;
+use super::encoding::Encoding;
+
+use std::io::{self, BufRead, Read};
+
+/// Bytes read at a time, at least.
+#[cfg(not(test))]
+const CHUNK: usize = 8 * 1024;
+/// Small enough in tests for records to be split across reads.
+#[cfg(test)]
+const CHUNK: usize = 7;
+
+/// Reads meshes from any [`BufRead`], handing nodes and elements on as they're read.
+///
+/// `$Nodes` and `$Elements` are parsed one record at a time from a small window
+/// of the file, so reading takes the memory of the mesh being built and little
+/// more. Other sections are handed on whole, so they're read whole, and so are
+/// 2.2 ASCII nodes and elements with [`ReadOptions::parallel`]. ASCII and binary
+/// files are both supported, and concatenated meshes are returned one by one.
+///
+/// ```no_run
+/// # fn main() -> msh_rw::parser::MshResult<()> {
+/// use msh_rw::parser::MshReader;
+/// use std::io::BufReader;
+///
+/// let file = BufReader::new(std::fs::File::open("mesh.msh")?);
+/// for msh in MshReader::new(file) {
+///     println!("{} nodes", msh?.nodes.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct MshReader<R> {
+    reader: R,
+    options: ReadOptions,
+    window: Window,
+    done: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// How much input a parser needs to be sure it didn't run out early.
+#[derive(Debug, Copy, Clone)]
+enum Extent<'e> {
+    /// A record, which is whole once anything follows it.
+    Record,
+    /// A whole section, up to its closing line.
+    Section(&'e [u8]),
+}
+
+impl<R: BufRead> MshReader<R> {
+    pub fn new(reader: R) -> MshReader<R> {
+        MshReader::with_options(reader, &ReadOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: &ReadOptions) -> MshReader<R> {
+        MshReader { reader, options: options.clone(), window: Window::default(), done: false, diagnostics: Vec::new() }
+    }
+
+    /// What was noticed in the meshes read so far.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    /// Hands the rest of the file to `visitor` instead of building meshes.
+    ///
+    /// Duplicate elements aren't merged, since that needs every element at once.
+    pub fn visit<V: MshVisitor>(&mut self, visitor: &mut V) -> MshResult<()> {
+        while self.visit_msh(visitor)?.is_some() {}
+        Ok(())
+    }
+
+    /// Hands the next mesh to `visitor`, returning its version, or `None` if there are no more.
+    pub(crate) fn visit_msh(&mut self, visitor: &mut dyn MshVisitor) -> MshResult<Option<MshVersion>> {
+        let result = self.try_visit_msh(visitor);
+        if result.is_err() {
+            self.done = true;
+        }
+        result
+    }
+
+    fn try_visit_msh(&mut self, visitor: &mut dyn MshVisitor) -> MshResult<Option<MshVersion>> {
+        // a mesh ends at the next one's header, so only the first can be missing
+        if self.done {
+            return Ok(None);
+        }
+        let header = self.parse(Extent::Section(b"$EndMeshFormat"), |i, _| msh_header(i))?;
+        let mut visit = Visit::new(visitor).check_nodes(self.options.check_nodes);
+        visit.header(header);
+        // large 2.2 ASCII sections can only be split across threads once read whole
+        let streamed = !(cfg!(feature = "parallel") && self.options.parallel && header == MshVersion::AsciiV22);
+        loop {
+            let len = self.window.line_len(&mut self.reader)?;
+            let line = &self.window.unread()[..len];
+            if line.starts_with(b"$MeshFormat") {
+                break;
+            }
+            // like for whole files, reading stops at a line that doesn't start a section
+            let end = match section_end(line) {
+                Some(end) => end,
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+            match end.as_slice() {
+                b"$EndNodes" if streamed => {
+                    self.window.consume(len);
+                    self.nodes(&mut visit, header)?;
+                }
+                b"$EndElements" if streamed => {
+                    self.window.consume(len);
+                    self.elements(&mut visit, header)?;
+                }
+                _ => {
+                    // binary data can look like the closing line, in which case the
+                    // section is parsed again once more of it is read
+                    let checkpoint = visit.checkpoint();
+                    self.parse(Extent::Section(&end), |i, d| {
+                        visit.retry_from(checkpoint);
+                        super::parse_section(&mut visit, header, i, d)
+                    })?;
+                }
+            }
+        }
+        Ok(Some(header))
+    }
+
+    fn nodes(&mut self, visit: &mut Visit, header: MshVersion) -> MshResult<()> {
+        match header {
+            MshVersion::AsciiV22 => self.nodes_msh2_ascii(visit),
+            MshVersion::AsciiV41 => self.nodes_msh4::<Ascii>(visit),
+            MshVersion::BinaryLeV22 => self.nodes_msh2_binary::<BinaryLe>(visit),
+            MshVersion::BinaryLeV41 => self.nodes_msh4::<BinaryLe>(visit),
+            MshVersion::BinaryBeV22 => self.nodes_msh2_binary::<BinaryBe>(visit),
+            MshVersion::BinaryBeV41 => self.nodes_msh4::<BinaryBe>(visit),
+        }
+    }
+
+    fn elements(&mut self, visit: &mut Visit, header: MshVersion) -> MshResult<()> {
+        match header {
+            MshVersion::AsciiV22 => self.elements_msh2_ascii(visit),
+            MshVersion::AsciiV41 => self.elements_msh4::<Ascii>(visit),
+            MshVersion::BinaryLeV22 => self.elements_msh2_binary::<BinaryLe>(visit),
+            MshVersion::BinaryLeV41 => self.elements_msh4::<BinaryLe>(visit),
+            MshVersion::BinaryBeV22 => self.elements_msh2_binary::<BinaryBe>(visit),
+            MshVersion::BinaryBeV41 => self.elements_msh4::<BinaryBe>(visit),
+        }
+    }
+
+    fn nodes_msh2_ascii(&mut self, visit: &mut Visit) -> MshResult<()> {
+        let count_line = self.window.mark();
+        let num_nodes = self.record("$Nodes", |i, _| as_text(i, terminated(parse_u64, end_of_line)))?;
+        let mut found = 0;
+        while !self.closing(b"$EndNodes")? {
+            let node = self.record("$Nodes", |i, _| as_text(i, parse_node_msh2))?;
+            visit.node(node);
+            found += 1;
+        }
+        self.check_count(count_line, "$Nodes", num_nodes, found)
+    }
+
+    fn elements_msh2_ascii(&mut self, visit: &mut Visit) -> MshResult<()> {
+        let count_line = self.window.mark();
+        let num_elts = self.record("$Elements", |i, _| as_text(i, terminated(parse_u64, end_of_line)))?;
+        // one element is reused for every record
+        let mut elt = MeshElt::new(0, MeshShape::Point, Vec::new());
+        let mut found = 0;
+        while !self.closing(b"$EndElements")? {
+            self.record("$Elements", |i, d| {
+                as_text(i, |i| {
+                    let (rest, _) = parse_element_msh2(i, &mut elt, d)?;
+                    check_nodes(i, &elt, visit, d)?;
+                    Ok((rest, ()))
+                })
+            })?;
+            visit.element(&elt);
+            found += 1;
+        }
+        self.check_count(count_line, "$Elements", num_elts, found)
+    }
+
+    fn nodes_msh2_binary<E: Encoding>(&mut self, visit: &mut Visit) -> MshResult<()> {
+        let num_nodes = self.record("$Nodes", |i, _| msh2_binary::ascii_count(i))?;
+        for _ in 0..num_nodes {
+            let node = self.record("$Nodes", |i, _| msh2_binary::parse_node::<E>(i))?;
+            visit.node(node);
+        }
+        self.record("$Nodes", |i, _| msh2_binary::section_footer("$EndNodes")(i))
+    }
+
+    fn elements_msh2_binary<E: Encoding>(&mut self, visit: &mut Visit) -> MshResult<()> {
+        let count_line = self.window.mark();
+        let num_elts = self.record("$Elements", |i, _| msh2_binary::ascii_count(i))?;
+        // elements are grouped in blocks sharing a type and number of tags
+        let mut tags = Vec::new();
+        let mut found = 0;
+        while found < num_elts {
+            let (mut elt, block_len, num_tags) =
+                self.record("$Elements", |i, _| msh2_binary::element_block_header::<E>(i))?;
+            for _ in 0..block_len {
+                self.record("$Elements", |i, d| {
+                    let (rest, _) = msh2_binary::parse_element::<E>(i, &mut elt, &mut tags, num_tags, d)?;
+                    check_nodes(i, &elt, visit, d)?;
+                    Ok((rest, ()))
+                })?;
+                visit.element(&elt);
+            }
+            found += block_len;
+        }
+        // the last block may run past the count
+        if found > num_elts {
+            self.check_count(count_line, "$Elements", num_elts as u64, found as u64)?;
+        }
+        self.record("$Elements", |i, _| msh2_binary::section_footer("$EndElements")(i))
+    }
+
+    fn nodes_msh4<E: Encoding>(&mut self, visit: &mut Visit) -> MshResult<()> {
+        let count_line = self.window.mark();
+        let (num_blocks, num_nodes) = self.record("$Nodes", |i, _| msh4::section_counts::<E>(i))?;
+        let mut tags = Vec::new();
+        let mut found = 0;
+        for _ in 0..num_blocks {
+            let (entity, parametric, block_len) = self.record("$Nodes", |i, _| msh4::node_block_header::<E>(i))?;
+            // all tags come first, followed by all coordinates
+            tags.clear();
+            for _ in 0..block_len {
+                tags.push(self.record("$Nodes", |i, _| E::size_t(i))?);
+            }
+            for &tag in &tags {
+                let node = self.record("$Nodes", |i, _| msh4::parse_node::<E>(i, entity, parametric))?;
+                visit.node(Node { tag, ..node });
+            }
+            found += block_len;
+        }
+        self.check_count(count_line, "$Nodes", num_nodes, found)?;
+        self.record("$Nodes", |i, _| msh4::section_footer("$EndNodes")(i))
+    }
+
+    fn elements_msh4<E: Encoding>(&mut self, visit: &mut Visit) -> MshResult<()> {
+        let count_line = self.window.mark();
+        let (num_blocks, num_elts) = self.record("$Elements", |i, _| msh4::section_counts::<E>(i))?;
+        let mut found = 0;
+        for _ in 0..num_blocks {
+            let (mut elt, block_len) = self.record("$Elements", |i, _| msh4::element_block_header::<E>(i, visit))?;
+            for _ in 0..block_len {
+                self.record("$Elements", |i, d| {
+                    let (rest, _) = msh4::parse_element::<E>(i, &mut elt)?;
+                    check_nodes(i, &elt, visit, d)?;
+                    Ok((rest, ()))
+                })?;
+                visit.element(&elt);
+            }
+            found += block_len;
+        }
+        self.check_count(count_line, "$Elements", num_elts, found)?;
+        self.record("$Elements", |i, _| msh4::section_footer("$EndElements")(i))
+    }
+
+    /// Parses a record of `section`.
+    fn record<T, F>(&mut self, section: &'static str, mut parse: F) -> MshResult<T>
+    where
+        F: for<'a> FnMut(&'a [u8], &Diagnostics<'a>) -> IResult<&'a [u8], T>,
+    {
+        self.parse(Extent::Record, |i, d| in_section(section, parse(i, d)))
+    }
+
+    /// Runs `parse` on what's left of the file, reading on and parsing again
+    /// while it may have run out of input.
+    ///
+    /// Each time, at least as much is read as was already there, so parsing
+    /// again takes as long as the first parse at most.
+    fn parse<T, F>(&mut self, extent: Extent, mut parse: F) -> MshResult<T>
+    where
+        F: for<'a> FnMut(&'a [u8], &Diagnostics<'a>) -> IResult<&'a [u8], T>,
+    {
+        let (offset, lines) = self.window.mark();
+        if let Extent::Section(end) = extent {
+            self.window.fill_through(&mut self.reader, end, 0)?;
+        }
+        loop {
+            let eof = self.window.eof;
+            let outcome = {
+                let input = self.window.unread();
+                let diagnostics = Diagnostics::following(input, &self.options, &self.diagnostics);
+                match parse(input, &diagnostics) {
+                    // parsers stop at the end of the input, which may not be the end of what they read
+                    Ok((rest, _)) if rest.is_empty() && !eof => Outcome::ReadOn(None),
+                    Ok((rest, out)) => Outcome::Parsed(input.len() - rest.len(), out, diagnostics.into_vec()),
+                    Err(err) => match cut_short(input, &err, extent) {
+                        Some(at) if !eof => Outcome::ReadOn(Some(at)),
+                        _ => Outcome::Failed(into_msh_error(input, err)),
+                    },
+                }
+            };
+            match outcome {
+                Outcome::Parsed(len, out, diagnostics) => {
+                    for mut diagnostic in diagnostics {
+                        diagnostic.location.shift(offset, lines);
+                        self.diagnostics.push(diagnostic);
+                    }
+                    self.window.consume(len);
+                    return Ok(out);
+                }
+                Outcome::ReadOn(at) => match (extent, at) {
+                    (Extent::Section(end), Some(at)) => self.window.fill_through(&mut self.reader, end, at)?,
+                    _ => self.window.fill(&mut self.reader)?,
+                },
+                Outcome::Failed(err) => return Err(err.shift(offset, lines)),
+            }
+        }
+    }
+
+    /// Consumes the next line if it's the closing line `end`.
+    fn closing(&mut self, end: &[u8]) -> io::Result<bool> {
+        let len = self.window.line_len(&mut self.reader)?;
+        let found = is_closing(&self.window.unread()[..len], end);
+        if found {
+            self.window.consume(len);
+        }
+        Ok(found)
+    }
+
+    /// Warns if a section's count at `count_line` doesn't match its entries, failing in strict mode.
+    fn check_count(
+        &mut self,
+        (offset, lines): (usize, usize),
+        section: &'static str,
+        expected: u64,
+        found: u64,
+    ) -> MshResult<()> {
+        if expected == found {
+            return Ok(());
+        }
+        // the count line may be long gone, so the warning is located from an empty input
+        let empty: &[u8] = &[];
+        let diagnostics = Diagnostics::new(empty, &self.options);
+        if let Err(err) = diagnostics.warn(empty, section, DiagnosticKind::CountMismatch { expected, found }) {
+            return Err(into_msh_error(empty, err).shift(offset, lines));
+        }
+        for mut diagnostic in diagnostics.into_vec() {
+            diagnostic.location.shift(offset, lines);
+            self.diagnostics.push(diagnostic);
+        }
+        Ok(())
+    }
+}
+
+enum Outcome<T> {
+    /// Parsed this many bytes.
+    Parsed(usize, T, Vec<Diagnostic>),
+    /// Ran out of input, failing here if it's known where.
+    ReadOn(Option<usize>),
+    Failed(MshError),
+}
+
+/// Where parsing failed if it may be for lack of input, which for records is
+/// when the rest of the line is missing and for sections when their closing line is.
+fn cut_short(input: &[u8], err: &nom::Err<ParseError<&[u8]>>, extent: Extent) -> Option<usize> {
+    let err = match err {
+        Err::Error(err) | Err::Failure(err) => err,
+        Err::Incomplete(_) => return Some(input.len()),
+    };
+    let at = Location::new(input, err.input, None).offset;
+    let cut_short = match extent {
+        Extent::Record => err.kind == ParseErrorKind::Truncated || !input[at..].contains(&b'\n'),
+        Extent::Section(end) => find_closing_line(input, at, end).is_err(),
+    };
+    if cut_short {
+        Some(at)
+    } else {
+        None
+    }
+}
+
+impl<R: BufRead> Iterator for MshReader<R> {
+    type Item = MshResult<Msh>;
+
+    fn next(&mut self) -> Option<MshResult<Msh>> {
+        let mut msh = Msh::new();
+        match self.visit_msh(&mut msh) {
+            Ok(Some(version)) => {
+                if self.options.merge_duplicate_elements && version.is_v22() {
+                    msh.merge_duplicate_elements();
+                }
+                Some(Ok(msh))
+            }
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// The part of the file read but not parsed yet.
+#[derive(Debug, Default)]
+struct Window {
+    buf: Vec<u8>,
+    /// Where the unparsed part of `buf` starts.
+    start: usize,
+    /// Bytes and newlines parsed so far.
+    offset: usize,
+    lines: usize,
+    eof: bool,
+}
+
+impl Window {
+    fn unread(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    /// Where parsing is in the file, as bytes and newlines before it.
+    fn mark(&self) -> (usize, usize) {
+        (self.offset, self.lines)
+    }
+
+    fn consume(&mut self, len: usize) {
+        let end = self.start + len;
+        self.lines += self.buf[self.start..end].iter().filter(|&&byte| byte == b'\n').count();
+        self.offset += len;
+        self.start = end;
+    }
+
+    /// Reads at least as much as is left unparsed, dropping what was parsed.
+    fn fill<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        self.buf.drain(..self.start);
+        self.start = 0;
+        let want = self.buf.len().max(CHUNK);
+        let read = reader.take(want as u64).read_to_end(&mut self.buf)?;
+        self.eof = read < want;
+        Ok(())
+    }
+
+    /// The length of the next line, reading on until it's whole.
+    fn line_len<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
+        let mut from = 0;
+        loop {
+            if let Some(at) = self.unread()[from..].iter().position(|&byte| byte == b'\n') {
+                return Ok(from + at + 1);
+            }
+            from = self.unread().len();
+            if self.eof {
+                return Ok(from);
+            }
+            self.fill(reader)?;
+        }
+    }
+
+    /// Reads on until a whole closing line `end` starts at or after `from`, or the file ends.
+    fn fill_through<R: Read>(&mut self, reader: &mut R, end: &[u8], mut from: usize) -> io::Result<()> {
+        while !self.eof {
+            match find_closing_line(self.unread(), from, end) {
+                Ok(()) => break,
+                Err(next) => from = next,
+            }
+            self.fill(reader)?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks for a whole closing line `end` starting at or after `from`, or else
+/// returns where to look again once more is read.
+fn find_closing_line(input: &[u8], from: usize, end: &[u8]) -> Result<(), usize> {
+    let mut line_start = if from == 0 || input[from - 1] == b'\n' { Some(from) } else { None };
+    for at in from..input.len() {
+        if input[at] == b'\n' {
+            if let Some(start) = line_start {
+                if is_closing(&input[start..=at], end) {
+                    return Ok(());
+                }
+            }
+            line_start = Some(at + 1);
+        }
+    }
+    Err(line_start.unwrap_or(input.len()))
+}
+
+fn is_closing(line: &[u8], end: &[u8]) -> bool {
+    line.starts_with(end) && line[end.len()..].iter().all(|byte| b" \t\r\n".contains(byte))
+}
+
+/// The closing line of the section `header_line` opens, e.g. `$EndNodes` for `$Nodes`.
+fn section_end(header_line: &[u8]) -> Option<Vec<u8>> {
+    let line = std::str::from_utf8(header_line).ok()?.trim_end();
+    if !line.starts_with('$') {
+        return None;
+    }
+    let name = &line[1..];
+    let is_name = !name.is_empty() && name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_');
+    if !is_name || name.starts_with("End") {
+        return None;
+    }
+    Some(format!("$End{}", name).into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::io::BufReader;
+    use std::rc::Rc;
+
+    const PROPS: &[&str] = &[
+        "props/v2/tetra-pgroup.msh",
+        "props/v2/views.msh",
+        "props/v2/periodic.msh",
+        "props/v4/square.msh",
+        "props/v4/partitioned.msh",
+        "props/v4/parametric.msh",
+    ];
+
+    #[test]
+    fn same_as_whole_file() {
+        for path in PROPS {
+            let file = std::fs::read(path).unwrap();
+            let msh = parse_msh_file(&file).unwrap();
+            for &storage in &[Storage::Ascii, Storage::BinaryLe, Storage::BinaryBe] {
+                let mut buffer = Vec::new();
+                msh[0].write_msh4(&mut buffer, storage).unwrap();
+                msh[0].write_msh2(&mut buffer, storage).unwrap();
+                // a tiny buffer splits lines across reads
+                let reader = MshReader::new(BufReader::with_capacity(7, buffer.as_slice()));
+                let streamed = reader.collect::<MshResult<Vec<_>>>().unwrap();
+                assert_eq!(streamed, parse_msh_file(&buffer).unwrap(), "{} ({:?})", path, storage);
+            }
+        }
+    }
+
+    #[test]
+    fn locations() {
+        let warnings = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Comments\nhi\n$EndComments\n$Nodes\n3\n1 0 0 0\n2 1 0 0\n$EndNodes\n";
+        let mut reader = MshReader::new(warnings.as_bytes());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().is_none());
+        assert_eq!(reader.into_diagnostics(), parse_msh_file_with(warnings, &ReadOptions::default()).unwrap().1);
+
+        let error = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n1\n1 0 0 0\n$EndNodes\n\
+                     $Elements\n2\n1 15 2 0 0 1\n2 999 2 0 0 1\n$EndElements\n";
+        let err = MshReader::new(error.as_bytes()).next().unwrap().unwrap_err();
+        assert_eq!(err.location(), parse_msh_file(error).unwrap_err().location());
+
+        // counts are checked once their section is read, ignored tags only reported once
+        let records = "$MeshFormat\n4.1 0 8\n$EndMeshFormat\n$Nodes\n1 2 1 1\n0 1 0 1\n1\n0 0 0\n$EndNodes\n\
+                       $MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n1\n1 0 0 0\n$EndNodes\n\
+                       $Elements\n2\n1 15 6 0 0 0 7 8 9 1\n2 15 6 0 0 0 7 8 9 1\n$EndElements\n";
+        let mut reader = MshReader::new(BufReader::with_capacity(7, records.as_bytes()));
+        assert_eq!(reader.by_ref().count(), 2);
+        let expected = parse_msh_file_with(records, &ReadOptions::default()).unwrap().1;
+        assert_eq!(expected.len(), 2);
+        assert_eq!(reader.into_diagnostics(), expected);
+        let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+        let err = MshReader::with_options(records.as_bytes(), &strict).next().unwrap().unwrap_err();
+        assert_eq!(err.location(), parse_msh_file_with(records, &strict).unwrap_err().location());
+    }
+
+    #[test]
+    fn end_tag_in_binary_data() {
+        let mut msh = Msh::new();
+        // 0x0a, "$EndNodes", 0x0a spread over the coordinates of two nodes
+        let bytes = *b"\x0a$EndNodes\x0a\0\0\0\0\0";
+        let x = f64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let y = f64::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15]]);
+        msh.nodes = vec![
+            Node { tag: 1, x, y, z: 0.0, entity: None, parametric: None },
+            Node { tag: 2, x: 1.0, y: 0.0, z: 0.0, entity: None, parametric: None },
+        ];
+        let mut buffer = Vec::new();
+        msh.write_msh2(&mut buffer, Storage::BinaryLe).unwrap();
+        let streamed = MshReader::new(buffer.as_slice()).next().unwrap().unwrap();
+        assert_eq!(streamed.nodes, msh.nodes);
+    }
+
+    #[test]
+    fn bad_header() {
+        for input in &[&b""[..], b"$Nodes\n0\n$EndNodes\n"] {
+            match MshReader::new(*input).next() {
+                Some(Err(MshError::Header { .. })) => (),
+                other => panic!("expected a header error, got {:?}", other),
+            }
+        }
+    }
+
+    /// Counts the bytes read through it.
+    struct Counted<'a> {
+        input: &'a [u8],
+        read: Rc<Cell<usize>>,
+    }
+
+    impl Read for Counted<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.input.read(buf)?;
+            self.read.set(self.read.get() + len);
+            Ok(len)
+        }
+    }
+
+    /// How much had been read when the first node came.
+    struct FirstNode {
+        read: Rc<Cell<usize>>,
+        at: Option<usize>,
+    }
+
+    impl MshVisitor for FirstNode {
+        fn on_node(&mut self, _: Node) {
+            self.at = self.at.or_else(|| Some(self.read.get()));
+        }
+    }
+
+    #[test]
+    fn nodes_handed_on_as_read() {
+        let mut msh = parse_msh_file(&std::fs::read("props/v4/square.msh").unwrap()).unwrap().remove(0);
+        let entity = msh.nodes[0].entity;
+        msh.nodes.extend((6..1000).map(|tag| Node { tag, x: 0.0, y: 0.0, z: 0.0, entity, parametric: None }));
+        for &storage in &[Storage::Ascii, Storage::BinaryLe, Storage::BinaryBe] {
+            let mut files = (Vec::new(), Vec::new());
+            msh.write_msh2(&mut files.0, storage).unwrap();
+            msh.write_msh4(&mut files.1, storage).unwrap();
+            for file in &[files.0, files.1] {
+                let read = Rc::new(Cell::new(0));
+                let reader = BufReader::with_capacity(7, Counted { input: file, read: read.clone() });
+                let mut first = FirstNode { read, at: None };
+                MshReader::new(reader).visit(&mut first).unwrap();
+                let end_nodes = file.windows(9).position(|window| window == b"$EndNodes").unwrap();
+                assert!(first.at.unwrap() < end_nodes / 2, "{:?}", storage);
+            }
+        }
+    }
+}
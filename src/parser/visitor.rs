@@ -0,0 +1,300 @@
+//! Handing a file's contents over as they are read, instead of building a [`Msh`].
+use super::MshVersion;
+use crate::*;
+
+use std::collections::{HashMap, HashSet};
+
+/// Receives the contents of `msh` files as they are read, see [`MshReader::visit`](super::MshReader::visit).
+///
+/// Every method does nothing by default, so visitors only implement what they
+/// need. [`Msh`] implements this trait to build itself.
+///
+/// ```no_run
+/// # fn main() -> msh_rw::parser::MshResult<()> {
+/// use msh_rw::{MeshElt, Tag};
+/// use msh_rw::parser::{MshReader, MshVisitor};
+/// use std::collections::HashMap;
+/// use std::io::BufReader;
+///
+/// /// Counts elements per physical group.
+/// #[derive(Default)]
+/// struct GroupSizes(HashMap<Tag, usize>);
+///
+/// impl MshVisitor for GroupSizes {
+///     fn on_element(&mut self, elt: &MeshElt) {
+///         for &group in &elt.physical_groups {
+///             *self.0.entry(group).or_insert(0) += 1;
+///         }
+///     }
+/// }
+///
+/// let mut sizes = GroupSizes::default();
+/// let file = BufReader::new(std::fs::File::open("mesh.msh")?);
+/// MshReader::new(file).visit(&mut sizes)?;
+/// # Ok(())
+/// # }
+/// ```
+#[allow(unused_variables)]
+pub trait MshVisitor {
+    /// The start of a mesh. Concatenated files have several.
+    fn on_header(&mut self, version: MshVersion) {}
+
+    fn on_physical_group(&mut self, group: PhysicalGroup) {}
+
+    fn on_entity(&mut self, entity: Entity) {}
+
+    /// Comes after the entities it lists.
+    fn on_partitioning(&mut self, partitioning: Partitioning) {}
+
+    fn on_node(&mut self, node: Node) {}
+
+    /// Elements are borrowed, so visitors that don't keep them don't pay for
+    /// allocating each one.
+    ///
+    /// Elements repeated once per physical group in 2.2 files come separately,
+    /// see [`Msh::merge_duplicate_elements`].
+    fn on_element(&mut self, elt: &MeshElt) {}
+
+    fn on_periodic_link(&mut self, link: PeriodicLink) {}
+
+    fn on_parametrizations(&mut self, parametrizations: Parametrizations) {}
+
+    fn on_interpolation_scheme(&mut self, scheme: InterpolationScheme) {}
+
+    /// A view holding the one step a data section stores. Later steps of the
+    /// same view come in separate calls.
+    fn on_data_view(&mut self, view: DataView) {}
+
+    fn on_unknown_section(&mut self, section: RawSection) {}
+}
+
+/// Builds the mesh. Concatenated meshes are appended to each other.
+impl MshVisitor for Msh {
+    fn on_physical_group(&mut self, group: PhysicalGroup) {
+        self.physical_groups.push(group);
+    }
+
+    fn on_entity(&mut self, entity: Entity) {
+        self.entities.push(entity);
+    }
+
+    fn on_partitioning(&mut self, partitioning: Partitioning) {
+        self.partitioning = self.partitioning.take().or(Some(partitioning));
+    }
+
+    fn on_node(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    fn on_element(&mut self, elt: &MeshElt) {
+        self.elts.push(elt.clone());
+    }
+
+    fn on_periodic_link(&mut self, link: PeriodicLink) {
+        self.periodic.push(link);
+    }
+
+    fn on_parametrizations(&mut self, parametrizations: Parametrizations) {
+        self.parametrizations.curves.extend(parametrizations.curves);
+        self.parametrizations.surfaces.extend(parametrizations.surfaces);
+    }
+
+    fn on_interpolation_scheme(&mut self, scheme: InterpolationScheme) {
+        self.interpolation_schemes.push(scheme);
+    }
+
+    fn on_data_view(&mut self, view: DataView) {
+        for step in view.steps {
+            crate::data::add_step(&mut self.views, view.name.clone(), view.kind, step);
+        }
+    }
+
+    fn on_unknown_section(&mut self, section: RawSection) {
+        self.unknown_sections.push(section);
+    }
+}
+
+/// Hands what the parsers read on to a visitor, remembering what later
+/// sections of the same mesh need.
+pub(crate) struct Visit<'v> {
+    visitor: &'v mut dyn MshVisitor,
+    /// Elements are checked against the nodes read before them, if asked to.
+    node_tags: Option<HashSet<Tag>>,
+    /// 4.1 elements take the physical groups of their entity.
+    entity_groups: HashMap<EntityId, Vec<Tag>>,
+    /// Number of calls made, and of calls to drop because an earlier attempt at
+    /// the same section already made them.
+    calls: usize,
+    skip: usize,
+}
+
+impl<'v> Visit<'v> {
+    pub(crate) fn new(visitor: &'v mut dyn MshVisitor) -> Visit<'v> {
+        Visit { visitor, node_tags: None, entity_groups: HashMap::new(), calls: 0, skip: 0 }
+    }
+
+    /// Keeps the tags of the nodes read, to check elements against, see [`ReadOptions::check_nodes`].
+    ///
+    /// [`ReadOptions::check_nodes`]: super::ReadOptions::check_nodes
+    pub(crate) fn check_nodes(mut self, check: bool) -> Visit<'v> {
+        self.node_tags = if check { Some(HashSet::new()) } else { None };
+        self
+    }
+
+    /// Whether a node with this tag was read, or nodes aren't checked.
+    pub(crate) fn has_node(&self, tag: Tag) -> bool {
+        match &self.node_tags {
+            Some(tags) => tags.contains(&tag),
+            None => true,
+        }
+    }
+
+    pub(crate) fn entity_groups(&self, id: EntityId) -> &[Tag] {
+        self.entity_groups.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Where the visitor is, to come back to with [`retry_from`](Visit::retry_from).
+    pub(crate) fn checkpoint(&self) -> usize {
+        self.calls
+    }
+
+    /// Parses again from `checkpoint`, without repeating the calls made since.
+    ///
+    /// Parsers are deterministic, so the same input leads to the same calls.
+    pub(crate) fn retry_from(&mut self, checkpoint: usize) {
+        self.skip = self.skip.max(self.calls);
+        self.calls = checkpoint;
+    }
+
+    fn visitor(&mut self) -> Option<&mut dyn MshVisitor> {
+        self.calls += 1;
+        if self.calls > self.skip {
+            Some(&mut *self.visitor)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn header(&mut self, version: MshVersion) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_header(version);
+        }
+    }
+
+    pub(crate) fn physical_group(&mut self, group: PhysicalGroup) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_physical_group(group);
+        }
+    }
+
+    pub(crate) fn entity(&mut self, entity: Entity) {
+        self.entity_groups.insert(entity.id(), entity.physical_groups.clone());
+        if let Some(visitor) = self.visitor() {
+            visitor.on_entity(entity);
+        }
+    }
+
+    pub(crate) fn partitioning(&mut self, partitioning: Partitioning) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_partitioning(partitioning);
+        }
+    }
+
+    pub(crate) fn node(&mut self, node: Node) {
+        if let Some(tags) = &mut self.node_tags {
+            tags.insert(node.tag);
+        }
+        if let Some(visitor) = self.visitor() {
+            visitor.on_node(node);
+        }
+    }
+
+    pub(crate) fn element(&mut self, elt: &MeshElt) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_element(elt);
+        }
+    }
+
+    pub(crate) fn periodic_link(&mut self, link: PeriodicLink) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_periodic_link(link);
+        }
+    }
+
+    pub(crate) fn parametrizations(&mut self, parametrizations: Parametrizations) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_parametrizations(parametrizations);
+        }
+    }
+
+    pub(crate) fn interpolation_scheme(&mut self, scheme: InterpolationScheme) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_interpolation_scheme(scheme);
+        }
+    }
+
+    pub(crate) fn data_step(&mut self, name: String, kind: DataKind, step: DataStep) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_data_view(DataView { name, kind, steps: vec![step] });
+        }
+    }
+
+    pub(crate) fn unknown_section(&mut self, section: RawSection) {
+        if let Some(visitor) = self.visitor() {
+            visitor.on_unknown_section(section);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{MshReader, ReadOptions};
+
+    #[derive(Default)]
+    struct Counts {
+        headers: usize,
+        nodes: usize,
+        elts: HashMap<MeshShape, usize>,
+        steps: usize,
+    }
+
+    impl MshVisitor for Counts {
+        fn on_header(&mut self, _: MshVersion) {
+            self.headers += 1;
+        }
+
+        fn on_node(&mut self, _: Node) {
+            self.nodes += 1;
+        }
+
+        fn on_element(&mut self, elt: &MeshElt) {
+            *self.elts.entry(elt.ty).or_insert(0) += 1;
+        }
+
+        fn on_data_view(&mut self, view: DataView) {
+            self.steps += view.steps.len();
+        }
+    }
+
+    #[test]
+    fn counts() {
+        for path in &["props/v2/basic-twice.msh", "props/v2/views.msh", "props/v4/square-bin.msh"] {
+            let file = std::fs::read(path).unwrap();
+            let mut counts = Counts::default();
+            MshReader::new(file.as_slice()).visit(&mut counts).unwrap();
+
+            let options = ReadOptions { merge_duplicate_elements: false, ..Default::default() };
+            let meshes = Msh::read_all_with(file.as_slice(), &options).unwrap().0;
+            assert_eq!(counts.headers, meshes.len());
+            assert_eq!(counts.nodes, meshes.iter().map(|msh| msh.nodes.len()).sum::<usize>());
+            let mut elts = HashMap::new();
+            for elt in meshes.iter().flat_map(|msh| &msh.elts) {
+                *elts.entry(elt.ty).or_insert(0) += 1;
+            }
+            assert_eq!(counts.elts, elts);
+            let steps = meshes.iter().flat_map(|msh| &msh.views).map(|view| view.steps.len()).sum::<usize>();
+            assert_eq!(counts.steps, steps);
+        }
+    }
+}
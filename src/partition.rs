@@ -0,0 +1,203 @@
+//! Partitioned meshes, from `$PartitionedEntities` (`msh` 4.1 only).
+use crate::*;
+use crate::parser::MshResult;
+
+use std::collections::HashSet;
+use std::path::Path;
+
+/// How a mesh was split into partitions.
+///
+/// The entities of a partitioned mesh are pieces of the model's entities, each
+/// kept in [`Msh::entities`] with its parent and partitions listed here.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Partitioning {
+    pub num_partitions: u64,
+    pub ghost_entities: Vec<GhostEntity>,
+    pub entities: Vec<PartitionedEntity>,
+}
+
+/// An entity holding the ghost cells of a partition.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GhostEntity {
+    pub tag: Tag,
+    pub partition: Tag,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionedEntity {
+    pub id: EntityId,
+    /// The model entity this one was cut from.
+    pub parent: EntityId,
+    /// Partitions sharing the entity, more than one on interfaces.
+    pub partitions: Vec<Tag>,
+}
+
+impl Partitioning {
+    pub fn entity(&self, id: EntityId) -> Option<&PartitionedEntity> {
+        self.entities.iter().find(|entity| entity.id == id)
+    }
+}
+
+impl Msh {
+    /// One mesh per partition, numbered from 1. Empty for meshes that aren't partitioned.
+    pub fn partitions(&self) -> Vec<Msh> {
+        let num_partitions = self.partitioning.as_ref().map_or(0, |partitioning| partitioning.num_partitions);
+        (1..=num_partitions).map(|partition| self.partition(partition)).collect()
+    }
+
+    /// The entities, nodes and elements of one partition, like Gmsh writes to
+    /// each file when splitting a partitioned mesh.
+    ///
+    /// Nodes on interfaces belong to every partition sharing them.
+    pub fn partition(&self, partition: Tag) -> Msh {
+        let mut msh = Msh::new();
+        msh.physical_groups = self.physical_groups.clone();
+        let partitioning = match &self.partitioning {
+            Some(partitioning) => partitioning,
+            None => return msh,
+        };
+        let entities: Vec<_> = partitioning
+            .entities
+            .iter()
+            .filter(|entity| entity.partitions.contains(&partition))
+            .cloned()
+            .collect();
+        let ids: HashSet<EntityId> = entities.iter().map(|entity| entity.id).collect();
+        msh.entities = self.entities.iter().filter(|entity| ids.contains(&entity.id())).cloned().collect();
+        msh.nodes = self
+            .nodes
+            .iter()
+            .filter(|node| node.entity.iter().any(|entity| ids.contains(entity)))
+            .copied()
+            .collect();
+        msh.elts = self
+            .elts
+            .iter()
+            .filter(|elt| elt.geometry.iter().any(|&tag| ids.contains(&EntityId { dim: elt.ty.dim(), tag })))
+            .cloned()
+            .collect();
+        msh.partitioning = Some(Partitioning {
+            num_partitions: partitioning.num_partitions,
+            ghost_entities: partitioning
+                .ghost_entities
+                .iter()
+                .filter(|ghost| ghost.partition == partition)
+                .copied()
+                .collect(),
+            entities,
+        });
+        msh
+    }
+
+    /// Joins the pieces of a partitioned mesh, e.g. the files Gmsh writes one per partition.
+    ///
+    /// Entities, nodes and elements found in several pieces, such as nodes on
+    /// interfaces, are kept once.
+    pub fn stitch<I: IntoIterator<Item = Msh>>(pieces: I) -> Msh {
+        let mut msh = Msh::new();
+        let mut node_tags = HashSet::new();
+        let mut elt_tags = HashSet::new();
+        for piece in pieces {
+            for group in piece.physical_groups {
+                if !msh.physical_groups.contains(&group) {
+                    msh.physical_groups.push(group);
+                }
+            }
+            for entity in piece.entities {
+                if msh.entity(entity.id()).is_none() {
+                    msh.entities.push(entity);
+                }
+            }
+            msh.nodes.extend(piece.nodes.into_iter().filter(|node| node_tags.insert(node.tag)));
+            msh.elts.extend(piece.elts.into_iter().filter(|elt| elt_tags.insert(elt.tag)));
+            if let Some(other) = piece.partitioning {
+                let partitioning = msh.partitioning.get_or_insert_with(Partitioning::default);
+                partitioning.num_partitions = partitioning.num_partitions.max(other.num_partitions);
+                for ghost in other.ghost_entities {
+                    if !partitioning.ghost_entities.contains(&ghost) {
+                        partitioning.ghost_entities.push(ghost);
+                    }
+                }
+                for entity in other.entities {
+                    match partitioning.entities.iter_mut().find(|known| known.id == entity.id) {
+                        Some(known) => {
+                            for partition in entity.partitions {
+                                if !known.partitions.contains(&partition) {
+                                    known.partitions.push(partition);
+                                }
+                            }
+                        }
+                        None => partitioning.entities.push(entity),
+                    }
+                }
+            }
+        }
+        msh
+    }
+
+    /// Reads the numbered files Gmsh writes for a partitioned `path`, i.e.
+    /// `mesh_1.msh` to `mesh_N.msh` for `mesh.msh`, and stitches them together.
+    ///
+    /// The number of files comes from the first one.
+    pub fn from_partition_files<P: AsRef<Path>>(path: P) -> MshResult<Msh> {
+        let path = path.as_ref();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().map_or(String::new(), |ext| format!(".{}", ext.to_string_lossy()));
+        let piece = |partition: u64| Msh::from_file(path.with_file_name(format!("{}_{}{}", stem, partition, extension)));
+        let first = piece(1)?;
+        let num_partitions = first.partitioning.as_ref().map_or(1, |partitioning| partitioning.num_partitions);
+        let mut pieces = vec![first];
+        for partition in 2..=num_partitions {
+            pieces.push(piece(partition)?);
+        }
+        Ok(Msh::stitch(pieces))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_tags(msh: &Msh) -> Vec<Tag> {
+        msh.nodes.iter().map(|node| node.tag).collect()
+    }
+
+    #[test]
+    fn partitions() {
+        let msh = Msh::from_file("props/v4/partitioned.msh").unwrap();
+        let partitioning = msh.partitioning.as_ref().unwrap();
+        assert_eq!(partitioning.ghost_entities, vec![GhostEntity { tag: 3, partition: 1 }]);
+        let interface = EntityId { dim: Dim::from_u8_unchecked(1), tag: 5 };
+        assert_eq!(partitioning.entity(interface).unwrap().partitions, vec![1, 2]);
+        assert_eq!(partitioning.entity(interface).unwrap().parent.tag, 1);
+        let partitions = msh.partitions();
+        assert_eq!(partitions.len(), 2);
+        // nodes 1 and 3 sit on the interface
+        assert_eq!(node_tags(&partitions[0]), vec![1, 3, 2]);
+        assert_eq!(node_tags(&partitions[1]), vec![1, 3, 4]);
+        assert_eq!(partitions[1].elts[0].physical_groups, vec![1]);
+    }
+
+    #[test]
+    fn stitch_partitions() {
+        let msh = Msh::from_file("props/v4/partitioned.msh").unwrap();
+        assert_eq!(Msh::stitch(msh.partitions()), msh);
+    }
+
+    #[test]
+    fn partition_files() {
+        let msh = Msh::from_file("props/v4/partitioned.msh").unwrap();
+        let dir = std::env::temp_dir().join(format!("msh-rw-partitions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (i, partition) in msh.partitions().iter().enumerate() {
+            let mut file = std::fs::File::create(dir.join(format!("square_{}.msh", i + 1))).unwrap();
+            partition.write_msh4(&mut file, Storage::BinaryLe).unwrap();
+        }
+        let stitched = Msh::from_partition_files(dir.join("square.msh"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(stitched.unwrap(), msh);
+    }
+}
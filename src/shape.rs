@@ -0,0 +1,249 @@
+//! Gmsh element types.
+use crate::Dim;
+
+macro_rules! shapes {
+    ($($shape:ident = $ty:literal, $dim:literal, $order:literal, $nodes:expr;)*) => {
+        /// A Gmsh element type.
+        ///
+        /// Higher-order variants are named after their number of nodes. Complete
+        /// and incomplete (serendipity) elements only differ in name when their
+        /// node counts clash, in which case the incomplete one ends in `I`.
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum MeshShape {
+            $($shape,)*
+        }
+
+        impl MeshShape {
+            /// Every element type, ordered by Gmsh type number.
+            pub const ALL: &'static [MeshShape] = &[$(MeshShape::$shape,)*];
+
+            /// Element type numbers as used by binary files.
+            pub fn from_gmsh_type(ty: u32) -> Option<MeshShape> {
+                match ty {
+                    $($ty => Some(MeshShape::$shape),)*
+                    _ => None,
+                }
+            }
+
+            pub fn gmsh_type(self) -> u32 {
+                match self {
+                    $(MeshShape::$shape => $ty,)*
+                }
+            }
+
+            pub fn dim(self) -> Dim {
+                match self {
+                    $(MeshShape::$shape => Dim::from_u8_unchecked($dim),)*
+                }
+            }
+
+            /// Polynomial order of the element's geometry.
+            pub fn order(self) -> u8 {
+                match self {
+                    $(MeshShape::$shape => $order,)*
+                }
+            }
+
+            /// Number of nodes per element, or `None` for polygons and polyhedra
+            /// whose node count varies.
+            pub fn num_nodes(self) -> Option<usize> {
+                match self {
+                    $(MeshShape::$shape => $nodes,)*
+                }
+            }
+        }
+    };
+}
+
+// name = Gmsh type, dimension, order, nodes
+shapes! {
+    Line = 1, 1, 1, Some(2);
+    Triangle = 2, 2, 1, Some(3);
+    Quad = 3, 2, 1, Some(4);
+    Tetrahedron = 4, 3, 1, Some(4);
+    Hexahedron = 5, 3, 1, Some(8);
+    Prism = 6, 3, 1, Some(6);
+    Pyramid = 7, 3, 1, Some(5);
+    Line3 = 8, 1, 2, Some(3);
+    Triangle6 = 9, 2, 2, Some(6);
+    Quad9 = 10, 2, 2, Some(9);
+    Tetrahedron10 = 11, 3, 2, Some(10);
+    Hexahedron27 = 12, 3, 2, Some(27);
+    Prism18 = 13, 3, 2, Some(18);
+    Pyramid14 = 14, 3, 2, Some(14);
+    Point = 15, 0, 0, Some(1);
+    Quad8 = 16, 2, 2, Some(8);
+    Hexahedron20 = 17, 3, 2, Some(20);
+    Prism15 = 18, 3, 2, Some(15);
+    Pyramid13 = 19, 3, 2, Some(13);
+    Triangle9 = 20, 2, 3, Some(9);
+    Triangle10 = 21, 2, 3, Some(10);
+    Triangle12 = 22, 2, 4, Some(12);
+    Triangle15 = 23, 2, 4, Some(15);
+    Triangle15I = 24, 2, 5, Some(15);
+    Triangle21 = 25, 2, 5, Some(21);
+    Line4 = 26, 1, 3, Some(4);
+    Line5 = 27, 1, 4, Some(5);
+    Line6 = 28, 1, 5, Some(6);
+    Tetrahedron20 = 29, 3, 3, Some(20);
+    Tetrahedron35 = 30, 3, 4, Some(35);
+    Tetrahedron56 = 31, 3, 5, Some(56);
+    Tetrahedron22 = 32, 3, 4, Some(22);
+    Tetrahedron28 = 33, 3, 5, Some(28);
+    Polygon = 34, 2, 1, None;
+    Polyhedron = 35, 3, 1, None;
+    Quad16 = 36, 2, 3, Some(16);
+    Quad25 = 37, 2, 4, Some(25);
+    Quad36 = 38, 2, 5, Some(36);
+    Quad12 = 39, 2, 3, Some(12);
+    Quad16I = 40, 2, 4, Some(16);
+    Quad20 = 41, 2, 5, Some(20);
+    Triangle28 = 42, 2, 6, Some(28);
+    Triangle36 = 43, 2, 7, Some(36);
+    Triangle45 = 44, 2, 8, Some(45);
+    Triangle55 = 45, 2, 9, Some(55);
+    Triangle66 = 46, 2, 10, Some(66);
+    Quad49 = 47, 2, 6, Some(49);
+    Quad64 = 48, 2, 7, Some(64);
+    Quad81 = 49, 2, 8, Some(81);
+    Quad100 = 50, 2, 9, Some(100);
+    Quad121 = 51, 2, 10, Some(121);
+    Triangle18 = 52, 2, 6, Some(18);
+    Triangle21I = 53, 2, 7, Some(21);
+    Triangle24 = 54, 2, 8, Some(24);
+    Triangle27 = 55, 2, 9, Some(27);
+    Triangle30 = 56, 2, 10, Some(30);
+    Quad24 = 57, 2, 6, Some(24);
+    Quad28 = 58, 2, 7, Some(28);
+    Quad32 = 59, 2, 8, Some(32);
+    Quad36I = 60, 2, 9, Some(36);
+    Quad40 = 61, 2, 10, Some(40);
+    Line7 = 62, 1, 6, Some(7);
+    Line8 = 63, 1, 7, Some(8);
+    Line9 = 64, 1, 8, Some(9);
+    Line10 = 65, 1, 9, Some(10);
+    Line11 = 66, 1, 10, Some(11);
+    LineBorder = 67, 1, 1, Some(2);
+    TriangleBorder = 68, 2, 1, Some(3);
+    PolygonBorder = 69, 2, 1, None;
+    LineChild = 70, 1, 1, Some(2);
+    Tetrahedron84 = 71, 3, 6, Some(84);
+    Tetrahedron120 = 72, 3, 7, Some(120);
+    Tetrahedron165 = 73, 3, 8, Some(165);
+    Tetrahedron220 = 74, 3, 9, Some(220);
+    Tetrahedron286 = 75, 3, 10, Some(286);
+    Tetrahedron34 = 79, 3, 6, Some(34);
+    Tetrahedron40 = 80, 3, 7, Some(40);
+    Tetrahedron46 = 81, 3, 8, Some(46);
+    Tetrahedron52 = 82, 3, 9, Some(52);
+    Tetrahedron58 = 83, 3, 10, Some(58);
+    Line1 = 84, 1, 0, Some(1);
+    Triangle1 = 85, 2, 0, Some(1);
+    Quad1 = 86, 2, 0, Some(1);
+    Tetrahedron1 = 87, 3, 0, Some(1);
+    Hexahedron1 = 88, 3, 0, Some(1);
+    Prism1 = 89, 3, 0, Some(1);
+    Prism40 = 90, 3, 3, Some(40);
+    Prism75 = 91, 3, 4, Some(75);
+    Hexahedron64 = 92, 3, 3, Some(64);
+    Hexahedron125 = 93, 3, 4, Some(125);
+    Hexahedron216 = 94, 3, 5, Some(216);
+    Hexahedron343 = 95, 3, 6, Some(343);
+    Hexahedron512 = 96, 3, 7, Some(512);
+    Hexahedron729 = 97, 3, 8, Some(729);
+    Hexahedron1000 = 98, 3, 9, Some(1000);
+    Hexahedron32 = 99, 3, 3, Some(32);
+    Hexahedron44 = 100, 3, 4, Some(44);
+    Hexahedron56 = 101, 3, 5, Some(56);
+    Hexahedron68 = 102, 3, 6, Some(68);
+    Hexahedron80 = 103, 3, 7, Some(80);
+    Hexahedron92 = 104, 3, 8, Some(92);
+    Hexahedron104 = 105, 3, 9, Some(104);
+    Prism126 = 106, 3, 5, Some(126);
+    Prism196 = 107, 3, 6, Some(196);
+    Prism288 = 108, 3, 7, Some(288);
+    Prism405 = 109, 3, 8, Some(405);
+    Prism550 = 110, 3, 9, Some(550);
+    Prism24 = 111, 3, 3, Some(24);
+    Prism33 = 112, 3, 4, Some(33);
+    Prism42 = 113, 3, 5, Some(42);
+    Prism51 = 114, 3, 6, Some(51);
+    Prism60 = 115, 3, 7, Some(60);
+    Prism69 = 116, 3, 8, Some(69);
+    Prism78 = 117, 3, 9, Some(78);
+    Pyramid30 = 118, 3, 3, Some(30);
+    Pyramid55 = 119, 3, 4, Some(55);
+    Pyramid91 = 120, 3, 5, Some(91);
+    Pyramid140 = 121, 3, 6, Some(140);
+    Pyramid204 = 122, 3, 7, Some(204);
+    Pyramid285 = 123, 3, 8, Some(285);
+    Pyramid385 = 124, 3, 9, Some(385);
+    Pyramid21 = 125, 3, 3, Some(21);
+    Pyramid29 = 126, 3, 4, Some(29);
+    Pyramid37 = 127, 3, 5, Some(37);
+    Pyramid45 = 128, 3, 6, Some(45);
+    Pyramid53 = 129, 3, 7, Some(53);
+    Pyramid61 = 130, 3, 8, Some(61);
+    Pyramid69 = 131, 3, 9, Some(69);
+    Pyramid1 = 132, 3, 0, Some(1);
+    PointSub = 133, 0, 1, Some(1);
+    LineSub = 134, 1, 1, Some(2);
+    TriangleSub = 135, 2, 1, Some(3);
+    TetrahedronSub = 136, 3, 1, Some(4);
+    Tetrahedron16 = 137, 3, 3, Some(16);
+    TriangleMini = 138, 2, 1, Some(4);
+    TetrahedronMini = 139, 3, 1, Some(5);
+    Trihedron = 140, 3, 1, Some(4);
+}
+
+impl MeshShape {
+    pub fn from_gmsh_label(label: &str) -> Option<MeshShape> {
+        label.parse().ok().and_then(MeshShape::from_gmsh_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gmsh_types_round_trip() {
+        for &shape in MeshShape::ALL {
+            assert_eq!(MeshShape::from_gmsh_type(shape.gmsh_type()), Some(shape));
+            assert_eq!(MeshShape::from_gmsh_label(&shape.gmsh_type().to_string()), Some(shape));
+        }
+        assert_eq!(MeshShape::ALL.len(), 137);
+        assert_eq!(MeshShape::from_gmsh_type(0), None);
+        assert_eq!(MeshShape::from_gmsh_type(76), None);
+        assert_eq!(MeshShape::from_gmsh_label("141"), None);
+    }
+
+    #[test]
+    fn high_order() {
+        let tri = MeshShape::from_gmsh_label("9").unwrap();
+        assert_eq!((tri, tri.dim().as_u8(), tri.order(), tri.num_nodes()), (MeshShape::Triangle6, 2, 2, Some(6)));
+        let hex = MeshShape::from_gmsh_type(98).unwrap();
+        assert_eq!((hex, hex.order(), hex.num_nodes()), (MeshShape::Hexahedron1000, 9, Some(1000)));
+        assert_eq!(MeshShape::Polygon.num_nodes(), None);
+    }
+
+    #[test]
+    fn lagrange_node_counts() {
+        // complete elements of order p have a full grid of nodes
+        for &shape in MeshShape::ALL {
+            let p = shape.order() as usize;
+            let complete = match shape.gmsh_type() {
+                1 | 8 | 26..=28 | 62..=66 => p + 1,
+                2 | 9 | 21 | 23 | 25 | 42..=46 => (p + 1) * (p + 2) / 2,
+                3 | 10 | 36..=38 | 47..=51 => (p + 1) * (p + 1),
+                4 | 11 | 29..=31 | 71..=75 => (p + 1) * (p + 2) * (p + 3) / 6,
+                5 | 12 | 92..=98 => (p + 1) * (p + 1) * (p + 1),
+                6 | 13 | 90 | 91 | 106..=110 => (p + 1) * (p + 1) * (p + 2) / 2,
+                7 | 14 | 118..=124 => (p + 1) * (p + 2) * (2 * p + 3) / 6,
+                _ => continue,
+            };
+            assert_eq!(shape.num_nodes(), Some(complete), "{:?}", shape);
+        }
+    }
+}
@@ -0,0 +1,1023 @@
+//! `msh` writers.
+use crate::*;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+
+/// How [`Msh::write`] writes a file: its version, storage and formatting, and
+/// which of the optional sections to include.
+///
+/// The default is an ASCII 4.1 file with every section, shortest round-trip
+/// numbers and `\n` line endings.
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use msh_rw::{FloatFormat, Msh, Storage, Version, WriteOptions};
+///
+/// let msh = Msh::new();
+/// let options = WriteOptions::new()
+///     .version(Version::V22)
+///     .storage(Storage::Ascii)
+///     .floats(FloatFormat::Fixed(6))
+///     .views(false)
+///     .crlf(true);
+/// msh.write(&mut std::fs::File::create("mesh.msh")?, &options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteOptions {
+    version: Version,
+    storage: Storage,
+    floats: FloatFormat,
+    crlf: bool,
+    physical_names: bool,
+    periodic: bool,
+    parametrizations: bool,
+    interpolation_schemes: bool,
+    views: bool,
+    unknown_sections: bool,
+}
+
+impl WriteOptions {
+    pub fn new() -> WriteOptions {
+        WriteOptions {
+            version: Version::V41,
+            storage: Storage::Ascii,
+            floats: FloatFormat::Shortest,
+            crlf: false,
+            physical_names: true,
+            periodic: true,
+            parametrizations: true,
+            interpolation_schemes: true,
+            views: true,
+            unknown_sections: true,
+        }
+    }
+
+    pub fn version(mut self, version: Version) -> WriteOptions {
+        self.version = version;
+        self
+    }
+
+    /// ASCII or binary, and for binary files the byte order.
+    pub fn storage(mut self, storage: Storage) -> WriteOptions {
+        self.storage = storage;
+        self
+    }
+
+    /// How ASCII files write floating point numbers.
+    pub fn floats(mut self, floats: FloatFormat) -> WriteOptions {
+        self.floats = floats;
+        self
+    }
+
+    /// End text lines with `\r\n` instead of `\n`. Binary values are written as they are.
+    pub fn crlf(mut self, crlf: bool) -> WriteOptions {
+        self.crlf = crlf;
+        self
+    }
+
+    /// Write `$PhysicalNames`. Elements and entities keep their physical group tags either way.
+    pub fn physical_names(mut self, include: bool) -> WriteOptions {
+        self.physical_names = include;
+        self
+    }
+
+    /// Write `$Periodic`.
+    pub fn periodic(mut self, include: bool) -> WriteOptions {
+        self.periodic = include;
+        self
+    }
+
+    /// Write `$Parametrizations`, which only 4.1 files have.
+    pub fn parametrizations(mut self, include: bool) -> WriteOptions {
+        self.parametrizations = include;
+        self
+    }
+
+    /// Write `$InterpolationScheme` sections.
+    pub fn interpolation_schemes(mut self, include: bool) -> WriteOptions {
+        self.interpolation_schemes = include;
+        self
+    }
+
+    /// Write the data sections holding the views.
+    pub fn views(mut self, include: bool) -> WriteOptions {
+        self.views = include;
+        self
+    }
+
+    /// Write back the sections that weren't interpreted when reading.
+    pub fn unknown_sections(mut self, include: bool) -> WriteOptions {
+        self.unknown_sections = include;
+        self
+    }
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions::new()
+    }
+}
+
+impl Msh {
+    /// Writes a file as `options` says.
+    ///
+    /// 4.1 meshes without `$Entities` (e.g. read from 2.2 files) get a minimal
+    /// set of entities built from their elements' geometry and physical groups.
+    pub fn write<W: Write>(&self, sink: &mut W, options: &WriteOptions) -> io::Result<()> {
+        write(self, self, sink, options)
+    }
+
+    pub fn write_msh2<W: Write>(&self, sink: &mut W, storage: Storage) -> io::Result<()> {
+        self.write(sink, &WriteOptions::new().version(Version::V22).storage(storage))
+    }
+
+    pub fn write_msh4<W: Write>(&self, sink: &mut W, storage: Storage) -> io::Result<()> {
+        self.write(sink, &WriteOptions::new().version(Version::V41).storage(storage))
+    }
+
+    /// Physical names are text in every version and storage.
+    fn write_physical_groups<W: Write>(&self, sink: &mut W) -> io::Result<()> {
+        if self.physical_groups.is_empty() {
+            return Ok(());
+        }
+        writeln!(sink, "$PhysicalNames")?;
+        writeln!(sink, "{}", self.physical_groups.len())?;
+        for group in &self.physical_groups {
+            writeln!(sink, "{} {} \"{}\"", group.dim.as_u8(), group.tag, group.name)?;
+        }
+        writeln!(sink, "$EndPhysicalNames")
+    }
+
+    /// Interpolation schemes are text in every version and storage, one matrix row per line.
+    fn write_interpolation_schemes<W: Write>(&self, sink: &mut Sink<W>, floats: FloatFormat) -> io::Result<()> {
+        for scheme in &self.interpolation_schemes {
+            writeln!(sink, "$InterpolationScheme")?;
+            writeln!(sink, "\"{}\"", scheme.name)?;
+            writeln!(sink, "{}", scheme.topologies.len())?;
+            for interpolation in &scheme.topologies {
+                writeln!(sink, "{}", interpolation.topology)?;
+                writeln!(sink, "{}", interpolation.matrices.len())?;
+                for matrix in &interpolation.matrices {
+                    writeln!(sink, "{} {}", matrix.rows, matrix.columns)?;
+                    let mut fields = Fields { sink: &mut *sink, storage: Storage::Ascii, floats, line_start: true };
+                    for row in matrix.values.chunks(matrix.columns.max(1)) {
+                        for &value in row {
+                            fields.double(value)?;
+                        }
+                        fields.end_line()?;
+                    }
+                }
+            }
+            writeln!(sink, "$EndInterpolationScheme")?;
+        }
+        Ok(())
+    }
+
+    /// One data section per step, with each view's steps kept together.
+    fn write_views<W: Write>(&self, sink: &mut Sink<W>, version: Version, storage: Storage, floats: FloatFormat) -> io::Result<()> {
+        for view in &self.views {
+            for step in &view.steps {
+                write_data_step(sink, view, step, version, storage, floats)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unknown sections are written back byte for byte, after the known ones.
+    fn write_unknown_sections<W: Write>(&self, sink: &mut Sink<W>) -> io::Result<()> {
+        for section in &self.unknown_sections {
+            writeln!(sink, "${}", section.name)?;
+            sink.raw(&section.contents)?;
+            writeln!(sink, "$End{}", section.name)?;
+        }
+        Ok(())
+    }
+
+    /// Periodic links are text in every 2.2 storage.
+    fn write_periodic_msh2<W: Write>(&self, sink: &mut W, floats: FloatFormat) -> io::Result<()> {
+        if self.periodic.is_empty() {
+            return Ok(());
+        }
+        writeln!(sink, "$Periodic")?;
+        writeln!(sink, "{}", self.periodic.len())?;
+        for link in &self.periodic {
+            writeln!(sink, "{} {} {}", link.dim.as_u8(), link.entity, link.master)?;
+            if let Some(affine) = &link.affine {
+                write!(sink, "Affine")?;
+                for &value in affine {
+                    sink.write_all(b" ")?;
+                    floats.write(sink, value)?;
+                }
+                writeln!(sink)?;
+            }
+            writeln!(sink, "{}", link.nodes.len())?;
+            for (slave, master) in &link.nodes {
+                writeln!(sink, "{} {}", slave, master)?;
+            }
+        }
+        writeln!(sink, "$EndPeriodic")
+    }
+
+    fn write_periodic_msh4<W: Write>(&self, fields: &mut Fields<W>) -> io::Result<()> {
+        if self.periodic.is_empty() {
+            return Ok(());
+        }
+        writeln!(fields.sink, "$Periodic")?;
+        fields.size_t(self.periodic.len() as u64)?;
+        fields.end_line()?;
+        for link in &self.periodic {
+            fields.int(link.dim.as_u8())?;
+            fields.int(link.entity)?;
+            fields.int(link.master)?;
+            fields.end_line()?;
+            let affine = link.affine.as_ref().map_or(&[][..], |affine| &affine[..]);
+            fields.size_t(affine.len() as u64)?;
+            for &value in affine {
+                fields.double(value)?;
+            }
+            fields.end_line()?;
+            fields.size_t(link.nodes.len() as u64)?;
+            fields.end_line()?;
+            for &(slave, master) in &link.nodes {
+                fields.size_t(slave)?;
+                fields.size_t(master)?;
+                fields.end_line()?;
+            }
+        }
+        fields.end_section("$EndPeriodic")
+    }
+
+    fn write_parametrizations_msh4<W: Write>(&self, fields: &mut Fields<W>) -> io::Result<()> {
+        let parametrizations = &self.parametrizations;
+        if parametrizations.is_empty() {
+            return Ok(());
+        }
+        writeln!(fields.sink, "$Parametrizations")?;
+        fields.size_t(parametrizations.curves.len() as u64)?;
+        fields.size_t(parametrizations.surfaces.len() as u64)?;
+        fields.end_line()?;
+        for curve in &parametrizations.curves {
+            fields.int(curve.tag)?;
+            fields.size_t(curve.nodes.len() as u64)?;
+            fields.end_line()?;
+            for node in &curve.nodes {
+                for &value in node {
+                    fields.double(value)?;
+                }
+                fields.end_line()?;
+            }
+        }
+        for surface in &parametrizations.surfaces {
+            fields.int(surface.tag)?;
+            fields.size_t(surface.nodes.len() as u64)?;
+            fields.size_t(surface.triangles.len() as u64)?;
+            fields.end_line()?;
+            for node in &surface.nodes {
+                let values = node.point.iter().chain(&node.uv).chain(&node.curvature_max).chain(&node.curvature_min);
+                for &value in values {
+                    fields.double(value)?;
+                }
+                fields.end_line()?;
+            }
+            for triangle in &surface.triangles {
+                for &index in triangle {
+                    fields.int(index)?;
+                }
+                fields.end_line()?;
+            }
+        }
+        fields.end_section("$EndParametrizations")
+    }
+
+}
+
+/// Where the writers take nodes and elements from, so that [`Msh`] and
+/// [`CompactMsh`] are written by the same code.
+pub(crate) trait MeshRecords {
+    fn num_nodes(&self) -> usize;
+    fn node(&self, i: usize) -> Node;
+    fn num_elts(&self) -> usize;
+    fn elt(&self, i: usize) -> EltRef<'_>;
+}
+
+/// The fields of a [`MeshElt`], borrowed from wherever the element is stored.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct EltRef<'a> {
+    pub(crate) tag: Tag,
+    pub(crate) ty: MeshShape,
+    pub(crate) nodes: &'a [Tag],
+    pub(crate) physical_groups: &'a [Tag],
+    pub(crate) geometry: Option<Tag>,
+    pub(crate) partitions: &'a [Tag],
+    pub(crate) ghost_partitions: &'a [Tag],
+    pub(crate) parent: Option<Tag>,
+    pub(crate) domain: Option<(Tag, Tag)>,
+}
+
+impl<'a> EltRef<'a> {
+    /// What the records 2.2 files repeat for each physical group share: everything
+    /// but the groups and the tag, since Gmsh numbers each copy separately.
+    pub(crate) fn duplicate_key(self) -> EltRef<'a> {
+        EltRef { tag: 0, physical_groups: &[], ..self }
+    }
+}
+
+impl<'a> From<&'a MeshElt> for EltRef<'a> {
+    fn from(elt: &'a MeshElt) -> EltRef<'a> {
+        EltRef {
+            tag: elt.tag,
+            ty: elt.ty,
+            nodes: &elt.nodes,
+            physical_groups: &elt.physical_groups,
+            geometry: elt.geometry,
+            partitions: &elt.partitions,
+            ghost_partitions: &elt.ghost_partitions,
+            parent: elt.parent,
+            domain: elt.domain,
+        }
+    }
+}
+
+impl MeshRecords for Msh {
+    fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn node(&self, i: usize) -> Node {
+        self.nodes[i]
+    }
+
+    fn num_elts(&self) -> usize {
+        self.elts.len()
+    }
+
+    fn elt(&self, i: usize) -> EltRef<'_> {
+        EltRef::from(&self.elts[i])
+    }
+}
+
+/// Writes the nodes and elements of `mesh` with everything else from `rest`.
+///
+/// Small writes are buffered here, so `sink` needn't be.
+pub(crate) fn write<M, W>(mesh: &M, rest: &Msh, sink: &mut W, options: &WriteOptions) -> io::Result<()>
+where
+    M: MeshRecords,
+    W: Write,
+{
+    let mut sink = Sink { inner: BufWriter::new(sink), crlf: options.crlf };
+    match options.version {
+        Version::V22 => write_msh2(mesh, rest, &mut sink, options)?,
+        Version::V41 => write_msh4(mesh, rest, &mut sink, options)?,
+    }
+    sink.flush()
+}
+
+fn write_msh2<M, W>(mesh: &M, rest: &Msh, sink: &mut Sink<W>, options: &WriteOptions) -> io::Result<()>
+where
+    M: MeshRecords,
+    W: Write,
+{
+    let (storage, floats) = (options.storage, options.floats);
+    write!(sink, "{}", MshHeader { version: Version::V22, storage })?;
+    if options.physical_names {
+        rest.write_physical_groups(sink)?;
+    }
+    write_nodes_msh2(mesh, sink, storage, floats)?;
+    write_elements_msh2(mesh, sink, storage)?;
+    if options.periodic {
+        rest.write_periodic_msh2(sink, floats)?;
+    }
+    write_extra_sections(rest, sink, Version::V22, options)
+}
+
+fn write_msh4<M, W>(mesh: &M, rest: &Msh, sink: &mut Sink<W>, options: &WriteOptions) -> io::Result<()>
+where
+    M: MeshRecords,
+    W: Write,
+{
+    let (storage, floats) = (options.storage, options.floats);
+    write!(sink, "{}", MshHeader{ version: Version::V41, storage })?;
+    if options.physical_names {
+        rest.write_physical_groups(sink)?;
+    }
+    let classes = Classification::new(mesh, &rest.entities);
+    let mut fields = Fields { sink, storage, floats, line_start: true };
+    match &rest.partitioning {
+        Some(partitioning) => write_partitioned_entities_msh4(&mut fields, &classes.entities, partitioning)?,
+        None => write_entities_msh4(&mut fields, &classes.entities)?,
+    }
+    write_nodes_msh4(mesh, &mut fields, &classes)?;
+    write_elements_msh4(mesh, &mut fields, &classes)?;
+    if options.periodic {
+        rest.write_periodic_msh4(&mut fields)?;
+    }
+    if options.parametrizations {
+        rest.write_parametrizations_msh4(&mut fields)?;
+    }
+    write_extra_sections(rest, fields.sink, Version::V41, options)
+}
+
+/// The sections after the mesh, the same in both versions.
+fn write_extra_sections<W: Write>(rest: &Msh, sink: &mut Sink<W>, version: Version, options: &WriteOptions) -> io::Result<()> {
+    if options.interpolation_schemes {
+        rest.write_interpolation_schemes(sink, options.floats)?;
+    }
+    if options.views {
+        rest.write_views(sink, version, options.storage, options.floats)?;
+    }
+    if options.unknown_sections {
+        rest.write_unknown_sections(sink)?;
+    }
+    Ok(())
+}
+
+fn write_nodes_msh2<M, W>(mesh: &M, sink: &mut Sink<W>, storage: Storage, floats: FloatFormat) -> io::Result<()>
+where
+    M: MeshRecords,
+    W: Write,
+{
+    writeln!(sink, "$Nodes")?;
+    writeln!(sink, "{}", mesh.num_nodes())?;
+    let mut fields = Fields { sink, storage, floats, line_start: true };
+    for node in (0..mesh.num_nodes()).map(|i| mesh.node(i)) {
+        fields.int(node.tag)?;
+        fields.double(node.x)?;
+        fields.double(node.y)?;
+        fields.double(node.z)?;
+        fields.end_line()?;
+    }
+    fields.end_section("$EndNodes")
+}
+
+/// Elements in several physical groups are repeated once per group, with the
+/// repeats getting new tags past the highest one.
+fn write_elements_msh2<M: MeshRecords, W: Write>(mesh: &M, sink: &mut Sink<W>, storage: Storage) -> io::Result<()> {
+    let elts = || (0..mesh.num_elts()).map(|i| mesh.elt(i));
+    let num_records: usize = elts().map(|elt| elt.physical_groups.len().max(1)).sum();
+    writeln!(sink, "$Elements")?;
+    writeln!(sink, "{}", num_records)?;
+    // elements hold no floats
+    let mut fields = Fields { sink, storage, floats: FloatFormat::Shortest, line_start: true };
+    let mut records = EltRecords { next_tag: elts().map(|elt| elt.tag).max().unwrap_or(0), tags: Vec::new() };
+    if storage == Storage::Ascii {
+        for elt in elts() {
+            records.write(&mut fields, elt, true)?;
+        }
+    } else {
+        // each block shares an element type and number of tags
+        for ((ty, num_tags), block) in runs(mesh.num_elts(), |i| {
+            let elt = mesh.elt(i);
+            (elt.ty, num_tags_msh2(&elt))
+        }) {
+            fields.int(ty.gmsh_type())?;
+            fields.int(block.clone().map(|i| mesh.elt(i).physical_groups.len().max(1)).sum::<usize>())?;
+            fields.int(num_tags)?;
+            for i in block {
+                records.write(&mut fields, mesh.elt(i), false)?;
+            }
+        }
+    }
+    fields.end_section("$EndElements")
+}
+
+fn write_nodes_msh4<M: MeshRecords, W: Write>(mesh: &M, fields: &mut Fields<W>, classes: &Classification) -> io::Result<()> {
+    writeln!(fields.sink, "$Nodes")?;
+    // nodes with and without parametric coordinates can't share a block
+    let blocks = runs(mesh.num_nodes(), |i| {
+        let node = mesh.node(i);
+        (classes.node_entity(&node), node.parametric.is_some())
+    });
+    write_block_counts(fields, blocks.len(), (0..mesh.num_nodes()).map(|i| mesh.node(i).tag))?;
+    for ((entity, parametric), block) in blocks {
+        fields.int(entity.dim.as_u8())?;
+        fields.int(entity.tag)?;
+        fields.int(parametric as u8)?;
+        fields.size_t(block.len() as u64)?;
+        fields.end_line()?;
+        for node in block.clone().map(|i| mesh.node(i)) {
+            fields.size_t(node.tag)?;
+            fields.end_line()?;
+        }
+        for node in block.map(|i| mesh.node(i)) {
+            fields.double(node.x)?;
+            fields.double(node.y)?;
+            fields.double(node.z)?;
+            if let Some(params) = &node.parametric {
+                for &param in &params[..entity.dim.as_u8() as usize] {
+                    fields.double(param)?;
+                }
+            }
+            fields.end_line()?;
+        }
+    }
+    fields.end_section("$EndNodes")
+}
+
+fn write_elements_msh4<M: MeshRecords, W: Write>(mesh: &M, fields: &mut Fields<W>, classes: &Classification) -> io::Result<()> {
+    writeln!(fields.sink, "$Elements")?;
+    let blocks = runs(mesh.num_elts(), |i| {
+        let elt = mesh.elt(i);
+        (classes.elt_entity(&elt), elt.ty)
+    });
+    write_block_counts(fields, blocks.len(), (0..mesh.num_elts()).map(|i| mesh.elt(i).tag))?;
+    for ((entity, ty), block) in blocks {
+        fields.int(entity.dim.as_u8())?;
+        fields.int(entity.tag)?;
+        fields.int(ty.gmsh_type())?;
+        fields.size_t(block.len() as u64)?;
+        fields.end_line()?;
+        for elt in block.map(|i| mesh.elt(i)) {
+            fields.size_t(elt.tag)?;
+            for &node in elt.nodes {
+                fields.size_t(node)?;
+            }
+            fields.end_line()?;
+        }
+    }
+    fields.end_section("$EndElements")
+}
+
+fn write_entities_msh4<W: Write>(fields: &mut Fields<W>, entities: &[Entity]) -> io::Result<()> {
+    writeln!(fields.sink, "$Entities")?;
+    write_entity_counts(fields, entities)?;
+    for dim in 0..4 {
+        for entity in entities.iter().filter(|entity| entity.dim.as_u8() == dim) {
+            fields.int(entity.tag)?;
+            write_entity_body(fields, entity)?;
+        }
+    }
+    fields.end_section("$EndEntities")
+}
+
+/// Entities missing from `partitioning` are written as their own parent, in no partition.
+fn write_partitioned_entities_msh4<W: Write>(
+    fields: &mut Fields<W>,
+    entities: &[Entity],
+    partitioning: &Partitioning,
+) -> io::Result<()> {
+    writeln!(fields.sink, "$PartitionedEntities")?;
+    fields.size_t(partitioning.num_partitions)?;
+    fields.end_line()?;
+    fields.size_t(partitioning.ghost_entities.len() as u64)?;
+    fields.end_line()?;
+    for ghost in &partitioning.ghost_entities {
+        fields.int(ghost.tag)?;
+        fields.int(ghost.partition)?;
+        fields.end_line()?;
+    }
+    write_entity_counts(fields, entities)?;
+    for dim in 0..4 {
+        for entity in entities.iter().filter(|entity| entity.dim.as_u8() == dim) {
+            let partitioned = partitioning.entity(entity.id());
+            let parent = partitioned.map_or(entity.id(), |partitioned| partitioned.parent);
+            let partitions = partitioned.map_or(&[][..], |partitioned| &partitioned.partitions[..]);
+            fields.int(entity.tag)?;
+            fields.int(parent.dim.as_u8())?;
+            fields.int(parent.tag)?;
+            fields.size_t(partitions.len() as u64)?;
+            for &partition in partitions {
+                fields.int(partition)?;
+            }
+            write_entity_body(fields, entity)?;
+        }
+    }
+    fields.end_section("$EndPartitionedEntities")
+}
+
+fn write_entity_counts<W: Write>(fields: &mut Fields<W>, entities: &[Entity]) -> io::Result<()> {
+    for dim in 0..4 {
+        fields.size_t(entities.iter().filter(|entity| entity.dim.as_u8() == dim).count() as u64)?;
+    }
+    fields.end_line()
+}
+
+/// Everything after an entity's tag: bounding box, physical groups and boundary.
+fn write_entity_body<W: Write>(fields: &mut Fields<W>, entity: &Entity) -> io::Result<()> {
+    let dim = entity.dim.as_u8();
+    let coords = if dim == 0 { &entity.min[..] } else { &[entity.min, entity.max].concat()[..] };
+    for &coord in coords {
+        fields.double(coord)?;
+    }
+    fields.size_t(entity.physical_groups.len() as u64)?;
+    for &group in &entity.physical_groups {
+        fields.int(group)?;
+    }
+    if dim != 0 {
+        fields.size_t(entity.boundary.len() as u64)?;
+        for &bounding in &entity.boundary {
+            fields.int(bounding)?;
+        }
+    }
+    fields.end_line()
+}
+
+fn write_data_step<W: Write>(
+    sink: &mut Sink<W>,
+    view: &DataView,
+    step: &DataStep,
+    version: Version,
+    storage: Storage,
+    floats: FloatFormat,
+) -> io::Result<()> {
+    // tags are text in every storage
+    writeln!(sink, "${}", view.kind.section())?;
+    let string_tags: Vec<_> = Some(&view.name).into_iter().chain(&step.interpolation_scheme).collect();
+    writeln!(sink, "{}", string_tags.len())?;
+    for string_tag in string_tags {
+        writeln!(sink, "\"{}\"", string_tag)?;
+    }
+    writeln!(sink, "1")?;
+    floats.write(sink, step.time)?;
+    writeln!(sink)?;
+    let mut int_tags = vec![step.time_step, step.num_components as u64, step.entries.len() as u64];
+    int_tags.extend(step.partition);
+    writeln!(sink, "{}", int_tags.len())?;
+    for int_tag in int_tags {
+        writeln!(sink, "{}", int_tag)?;
+    }
+    let mut fields = Fields { sink, storage, floats, line_start: true };
+    for entry in &step.entries {
+        match version {
+            Version::V22 => fields.int(entry.tag)?,
+            Version::V41 => fields.size_t(entry.tag)?,
+        }
+        if view.kind == DataKind::ElementNode {
+            fields.int(entry.values.len() / step.num_components.max(1))?;
+        }
+        for &value in &entry.values {
+            fields.double(value)?;
+        }
+        fields.end_line()?;
+    }
+    fields.end_section(&format!("$End{}", view.kind.section()))
+}
+
+/// Number of blocks, number of items and their min and max tags.
+fn write_block_counts<W, I>(fields: &mut Fields<W>, num_blocks: usize, tags: I) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = Tag> + Clone,
+{
+    fields.size_t(num_blocks as u64)?;
+    fields.size_t(tags.clone().count() as u64)?;
+    fields.size_t(tags.clone().min().unwrap_or(0))?;
+    fields.size_t(tags.max().unwrap_or(0))?;
+    fields.end_line()
+}
+
+/// Splits the indices up to `len` into runs of consecutive indices sharing a key.
+fn runs<K: PartialEq>(len: usize, key: impl Fn(usize) -> K) -> Vec<(K, Range<usize>)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let first_key = key(start);
+        let end = (start + 1..len).find(|&i| key(i) != first_key).unwrap_or(len);
+        runs.push((first_key, start..end));
+        start = end;
+    }
+    runs
+}
+
+/// Which entity each node and element belongs to when writing 4.1 files.
+struct Classification {
+    entities: Vec<Entity>,
+    /// Entity tags for elements without geometry, indexed by dimension and then
+    /// physical groups, so that each element keeps its own groups.
+    untagged: [HashMap<Vec<Tag>, Tag>; 4],
+    /// Fallback for nodes without an entity: the lowest-dimensional entity using them.
+    node_entities: HashMap<Tag, EntityId>,
+    /// Entity for nodes no element uses.
+    orphan: EntityId,
+}
+
+impl Classification {
+    fn new<M: MeshRecords>(mesh: &M, entities: &[Entity]) -> Classification {
+        let elts = || (0..mesh.num_elts()).map(|i| mesh.elt(i));
+        let mut next = [1; 4];
+        for elt in elts() {
+            let dim = elt.ty.dim().as_u8() as usize;
+            next[dim] = next[dim].max(elt.geometry.unwrap_or(0) + 1);
+        }
+        let mut untagged: [HashMap<Vec<Tag>, Tag>; 4] = Default::default();
+        for elt in elts().filter(|elt| elt.geometry.is_none()) {
+            let dim = elt.ty.dim().as_u8() as usize;
+            if !untagged[dim].contains_key(elt.physical_groups) {
+                untagged[dim].insert(elt.physical_groups.to_vec(), next[dim]);
+                next[dim] += 1;
+            }
+        }
+        let mut classes = Classification {
+            entities: entities.to_vec(),
+            untagged,
+            node_entities: HashMap::new(),
+            orphan: EntityId { dim: Dim::from_u8_unchecked(0), tag: 1 },
+        };
+        for elt in elts() {
+            let entity = classes.elt_entity(&elt);
+            for &node in elt.nodes {
+                let class = classes.node_entities.entry(node).or_insert(entity);
+                if entity.dim.as_u8() < class.dim.as_u8() {
+                    *class = entity;
+                }
+            }
+        }
+        if entities.is_empty() {
+            classes.entities = classes.implied_entities(mesh);
+        }
+        if let Some(entity) = classes.entities.last() {
+            classes.orphan = entity.id();
+        } else if mesh.num_nodes() > 0 {
+            let node = mesh.node(0);
+            // a mesh of bare nodes still needs an entity to hold them
+            classes.entities.push(Entity {
+                dim: classes.orphan.dim,
+                tag: classes.orphan.tag,
+                min: [node.x, node.y, node.z],
+                max: [node.x, node.y, node.z],
+                physical_groups: Vec::new(),
+                boundary: Vec::new(),
+            });
+        }
+        classes
+    }
+
+    fn elt_entity(&self, elt: &EltRef) -> EntityId {
+        let dim = elt.ty.dim();
+        let untagged = || self.untagged[dim.as_u8() as usize][elt.physical_groups];
+        EntityId { dim, tag: elt.geometry.unwrap_or_else(untagged) }
+    }
+
+    fn node_entity(&self, node: &Node) -> EntityId {
+        node.entity
+            .or_else(|| self.node_entities.get(&node.tag).copied())
+            .unwrap_or(self.orphan)
+    }
+
+    /// One entity per element geometry, or per dimension and physical groups for
+    /// elements without one, bounding its elements' nodes.
+    fn implied_entities<M: MeshRecords>(&self, mesh: &M) -> Vec<Entity> {
+        let coords: HashMap<Tag, [f64; 3]> = (0..mesh.num_nodes())
+            .map(|i| mesh.node(i))
+            .map(|node| (node.tag, [node.x, node.y, node.z]))
+            .collect();
+        let mut entities: Vec<Entity> = Vec::new();
+        let mut index = HashMap::new();
+        for elt in (0..mesh.num_elts()).map(|i| mesh.elt(i)) {
+            let id = self.elt_entity(&elt);
+            let i = *index.entry(id).or_insert_with(|| {
+                entities.push(Entity {
+                    dim: id.dim,
+                    tag: id.tag,
+                    min: [f64::INFINITY; 3],
+                    max: [f64::NEG_INFINITY; 3],
+                    physical_groups: Vec::new(),
+                    boundary: Vec::new(),
+                });
+                entities.len() - 1
+            });
+            let entity = &mut entities[i];
+            for &group in elt.physical_groups {
+                if !entity.physical_groups.contains(&group) {
+                    entity.physical_groups.push(group);
+                }
+            }
+            for point in elt.nodes.iter().filter_map(|node| coords.get(node)) {
+                for (axis, &coord) in point.iter().enumerate() {
+                    entity.min[axis] = entity.min[axis].min(coord);
+                    entity.max[axis] = entity.max[axis].max(coord);
+                }
+            }
+        }
+        for entity in &mut entities {
+            // elements pointing at missing nodes leave an empty box
+            if entity.min[0] > entity.max[0] {
+                entity.min = [0.0; 3];
+                entity.max = [0.0; 3];
+            }
+        }
+        entities
+    }
+}
+
+/// Buffers what the writers write, ending text lines as [`WriteOptions::crlf`] says.
+///
+/// Binary values are written with [`raw`](Sink::raw), so that their bytes are
+/// never taken for newlines.
+struct Sink<W: Write> {
+    inner: BufWriter<W>,
+    crlf: bool,
+}
+
+impl<W: Write> Sink<W> {
+    fn raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)
+    }
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, text: &[u8]) -> io::Result<usize> {
+        if !self.crlf {
+            return self.inner.write(text);
+        }
+        let mut lines = text.split(|&byte| byte == b'\n');
+        if let Some(first) = lines.next() {
+            self.inner.write_all(first)?;
+        }
+        for line in lines {
+            self.inner.write_all(b"\r\n")?;
+            self.inner.write_all(line)?;
+        }
+        Ok(text.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes values space-separated for ASCII and packed for binary storage.
+struct Fields<'a, W: Write> {
+    sink: &'a mut Sink<W>,
+    storage: Storage,
+    floats: FloatFormat,
+    line_start: bool,
+}
+
+impl<'a, W: Write> Fields<'a, W> {
+    /// Only binary ints are limited to 32 bits, text is written in full.
+    fn int<T>(&mut self, value: T) -> io::Result<()>
+    where
+        T: Copy + Display + itoa::Integer,
+        i32: TryFrom<T>,
+    {
+        let binary = |value: T| i32::try_from(value).map_err(|_| out_of_range(value, "int"));
+        match self.storage {
+            Storage::Ascii => self.ascii(itoa::Buffer::new().format(value)),
+            Storage::BinaryLe => self.sink.raw(&binary(value)?.to_le_bytes()),
+            Storage::BinaryBe => self.sink.raw(&binary(value)?.to_be_bytes()),
+        }
+    }
+
+    fn size_t(&mut self, value: u64) -> io::Result<()> {
+        match self.storage {
+            Storage::Ascii => self.ascii(itoa::Buffer::new().format(value)),
+            Storage::BinaryLe => self.sink.raw(&value.to_le_bytes()),
+            Storage::BinaryBe => self.sink.raw(&value.to_be_bytes()),
+        }
+    }
+
+    fn double(&mut self, value: f64) -> io::Result<()> {
+        match self.storage {
+            Storage::Ascii => {
+                self.separate()?;
+                self.floats.write(self.sink, value)
+            }
+            Storage::BinaryLe => self.sink.raw(&value.to_le_bytes()),
+            Storage::BinaryBe => self.sink.raw(&value.to_be_bytes()),
+        }
+    }
+
+    fn ascii(&mut self, text: &str) -> io::Result<()> {
+        self.separate()?;
+        self.sink.write_all(text.as_bytes())
+    }
+
+    /// Puts a space before every value but the first on a line.
+    fn separate(&mut self) -> io::Result<()> {
+        if !self.line_start {
+            self.sink.write_all(b" ")?;
+        }
+        self.line_start = false;
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        self.line_start = true;
+        match self.storage {
+            Storage::Ascii => writeln!(self.sink),
+            Storage::BinaryLe | Storage::BinaryBe => Ok(()),
+        }
+    }
+
+    fn end_section(&mut self, end: &str) -> io::Result<()> {
+        // binary data is followed by a newline before the closing tag
+        if self.storage != Storage::Ascii {
+            writeln!(self.sink)?;
+        }
+        writeln!(self.sink, "{}", end)
+    }
+}
+
+/// How ASCII files write floating point numbers. Binary files store them as they are.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// The fewest digits that read back as the same number, e.g. `0.1`, `2` or
+    /// `1e-7`, so that files read back exactly.
+    Shortest,
+    /// This many digits after the decimal point, e.g. `0.100` for 3.
+    Fixed(usize),
+    /// Scientific notation with this many digits after the decimal point, e.g. `1.000e-1` for 3.
+    Scientific(usize),
+}
+
+impl FloatFormat {
+    fn write<W: Write>(self, sink: &mut W, value: f64) -> io::Result<()> {
+        match self {
+            FloatFormat::Shortest if value.is_finite() => {
+                let mut buffer = ryu::Buffer::new();
+                // `2` reads back as exactly as `2.0` does
+                let text = buffer.format_finite(value).trim_end_matches(".0");
+                sink.write_all(text.as_bytes())
+            }
+            FloatFormat::Shortest => write!(sink, "{}", value),
+            FloatFormat::Fixed(digits) => write!(sink, "{:.*}", digits, value),
+            FloatFormat::Scientific(digits) => write!(sink, "{:.*e}", digits, value),
+        }
+    }
+}
+
+fn out_of_range<T: Display>(value: T, ty: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("{} doesn't fit in an msh {}", value, ty))
+}
+
+/// Writes 2.2 element records, one per physical group.
+struct EltRecords {
+    /// The last tag given to a repeated element.
+    next_tag: Tag,
+    /// Reused for the tags of each record.
+    tags: Vec<i64>,
+}
+
+impl EltRecords {
+    /// ASCII records also give the element type and number of tags, which binary
+    /// files give once per block.
+    fn write<W: Write>(&mut self, fields: &mut Fields<W>, elt: EltRef, with_type: bool) -> io::Result<()> {
+        let groups = if elt.physical_groups.is_empty() { &[0][..] } else { elt.physical_groups };
+        for (i, &group) in groups.iter().enumerate() {
+            let tag = if i == 0 {
+                elt.tag
+            } else {
+                self.next_tag += 1;
+                self.next_tag
+            };
+            elt_tags_msh2(&elt, group, &mut self.tags)?;
+            fields.int(tag)?;
+            if with_type {
+                fields.int(elt.ty.gmsh_type())?;
+                fields.int(self.tags.len())?;
+            }
+            for &tag in &self.tags {
+                fields.int(tag)?;
+            }
+            for &node in elt.nodes {
+                fields.int(node)?;
+            }
+            fields.end_line()?;
+        }
+        Ok(())
+    }
+}
+
+/// Physical group and geometry, with zero meaning none, then any partitions
+/// (negative for ghosts) followed by the parent or else the domain.
+fn elt_tags_msh2(elt: &EltRef, physical_group: Tag, tags: &mut Vec<i64>) -> io::Result<()> {
+    // even ASCII tags are read back as signed, since ghost partitions are negative
+    let int = |tag: Tag| i64::try_from(tag).map_err(|_| out_of_range(tag, "element tag"));
+    tags.clear();
+    tags.push(int(physical_group)?);
+    tags.push(int(elt.geometry.unwrap_or(0))?);
+    let num_partitions = elt.partitions.len() + elt.ghost_partitions.len();
+    if num_partitions > 0 || elt.parent.is_some() || elt.domain.is_some() {
+        tags.push(num_partitions as i64);
+        for &partition in elt.partitions {
+            tags.push(int(partition)?);
+        }
+        for &partition in elt.ghost_partitions {
+            tags.push(-int(partition)?);
+        }
+    }
+    match (elt.parent, elt.domain) {
+        (Some(parent), _) => tags.push(int(parent)?),
+        (None, Some((first, second))) => tags.extend(&[int(first)?, int(second)?]),
+        (None, None) => (),
+    }
+    Ok(())
+}
+
+/// The number of tags [`elt_tags_msh2`] gives `elt`, the same for each of its groups.
+fn num_tags_msh2(elt: &EltRef) -> usize {
+    let num_partitions = elt.partitions.len() + elt.ghost_partitions.len();
+    let partitioned = num_partitions > 0 || elt.parent.is_some() || elt.domain.is_some();
+    let num_extra = match (elt.parent, elt.domain) {
+        (Some(_), _) => 1,
+        (None, Some(_)) => 2,
+        (None, None) => 0,
+    };
+    2 + if partitioned { 1 + num_partitions } else { 0 } + num_extra
+}